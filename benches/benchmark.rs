@@ -4,12 +4,15 @@ use std::{
     hint::black_box,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
+    time::{Duration, Instant},
 };
 
 const CAPACITY: usize = 1024;
 const OPERATIONS: usize = 1000;
+///Round trips timed one by one for the latency percentiles, after criterion is done
+const LATENCY_SAMPLES: usize = 10_000;
 
 #[allow(clippy::redundant_closure)]
 fn criterion_benchmark(c: &mut Criterion) {
@@ -30,6 +33,30 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 
     group.finish();
+
+    let mut group = c.benchmark_group("Latency");
+    latency_benchmark(
+        &mut group,
+        "MutexRingBuffer",
+        MutexRingBuffer::<u64, CAPACITY>::new,
+        |b, v| b.push(v).is_ok(),
+        |b| b.pop(),
+    );
+    latency_benchmark(
+        &mut group,
+        "AtomicRingBufferSpsc",
+        AtomicRingBufferSpsc::<u64, CAPACITY>::new,
+        |b, v| b.push(v).is_ok(),
+        |b| b.pop(),
+    );
+    latency_benchmark(
+        &mut group,
+        "AtomicRingBufferMpmc",
+        AtomicRingBufferMpmc::<u64, CAPACITY>::new,
+        |b, v| b.push(v).is_ok(),
+        |b| b.pop(),
+    );
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -174,3 +201,78 @@ fn atomic_ring_buffer_mpmc_benchmark() {
         h.join().unwrap();
     }
 }
+
+///Ping pong through a request and a response buffer with an echo thread on the other
+///side. Once criterion is done, a fixed number of round trips is timed one by one so
+///p50/p99 can be printed next to criterion's numbers, which only show the spread between
+///samples. Criterion's warm-up doesn't end up in the percentiles that way
+fn latency_benchmark<B: Clone + Send + 'static>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    name: &str,
+    new: fn() -> B,
+    push: fn(&B, u64) -> bool,
+    pop: fn(&B) -> Option<u64>,
+) {
+    let request = new();
+    let response = new();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let echo = std::thread::spawn({
+        let (request, response, stop) = (request.clone(), response.clone(), stop.clone());
+        move || {
+            while !stop.load(Ordering::Relaxed) {
+                if let Some(value) = pop(&request) {
+                    while !push(&response, value) {
+                        std::hint::spin_loop();
+                    }
+                } else {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+    });
+
+    let round_trip = |value: u64| {
+        while !push(&request, value) {
+            std::hint::spin_loop();
+        }
+        loop {
+            if let Some(echoed) = pop(&response) {
+                break black_box(echoed);
+            }
+            std::hint::spin_loop();
+        }
+    };
+
+    let mut ran = false;
+    group.bench_function(name, |b| {
+        ran = true;
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            for i in 0..iters {
+                round_trip(i);
+            }
+            start.elapsed()
+        })
+    });
+
+    //skipped when the benchmark was filtered out
+    if ran {
+        let mut samples: Vec<Duration> = (0..LATENCY_SAMPLES as u64)
+            .map(|i| {
+                let start = Instant::now();
+                round_trip(i);
+                start.elapsed()
+            })
+            .collect();
+        samples.sort_unstable();
+        println!(
+            "Latency/{name}: p50 {:?} p99 {:?}",
+            samples[samples.len() / 2],
+            samples[samples.len() * 99 / 100]
+        );
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    echo.join().unwrap();
+}
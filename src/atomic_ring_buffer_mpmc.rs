@@ -1,9 +1,35 @@
-use std::cell::UnsafeCell;
-use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, fence};
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
+use alloc::vec::Vec;
+#[cfg(feature = "hybrid")]
+use core::sync::atomic::AtomicBool;
+#[cfg(feature = "hybrid")]
+use parking_lot::{Mutex, MutexGuard};
+
+#[cfg(feature = "record")]
+use crate::op_log::{Op, OpLog, OpRecord};
+use crate::ordering::{ACQUIRE, RELAXED, RELEASE};
 use crate::primitives::Arc;
-use crate::{Backoff, Padded};
+use crate::{Backoff, DropObserver, Geometry, Padded, PushBatchError, SlotState, TryPushError};
+
+///Failed pushes between clock reads in `push_deadline`, reading the clock is slower
+///than a push attempt
+#[cfg(feature = "std")]
+const DEADLINE_CHECK_INTERVAL: usize = 16;
+
+///Failed CAS attempts after which an operation falls back to the slow path
+#[cfg(feature = "hybrid")]
+const HYBRID_CAS_LIMIT: usize = 64;
+
+///Failed CAS attempts a single operation may take before `debug-checks` assumes a livelock
+#[cfg(feature = "debug-checks")]
+const CAS_RETRY_LIMIT: usize = 1 << 20;
 
 #[repr(align(64))]
 struct Slot<T> {
@@ -11,138 +37,943 @@ struct Slot<T> {
     data: UnsafeCell<MaybeUninit<T>>,
 }
 
+///A buffered slot whose sequence does not match its position, found by `diagnose_stall`
+#[cfg(feature = "debug-checks")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StallReport {
+    ///Index of the slot in the backing array
+    pub slot: usize,
+    ///Logical position the slot holds, between tail and head
+    pub position: usize,
+    ///What the sequence should be for a published item at `position`
+    pub expected_sequence: usize,
+    pub sequence: usize,
+}
+
+///Held by the one operation on the slow path, clears `escalated` before the lock goes
+#[cfg(feature = "hybrid")]
+struct Escalation<'a> {
+    escalated: &'a AtomicBool,
+    _lock: MutexGuard<'a, ()>,
+}
+#[cfg(feature = "hybrid")]
+impl Drop for Escalation<'_> {
+    fn drop(&mut self) {
+        self.escalated.store(false, RELAXED);
+    }
+}
+
 ///Uses atomic's instead of mutexes
 pub struct AtomicRingBufferMpmc<T, const N: usize> {
     head: Padded<AtomicUsize>,
     tail: Padded<AtomicUsize>,
     buffer: [Slot<T>; N],
+    #[cfg(feature = "std")]
+    drop_observer: OnceLock<DropObserver<T>>,
+    #[cfg(feature = "metrics")]
+    metrics: Padded<crate::metrics::Counters>,
+    ///Taken by operations that keep losing the CAS race. They keep retrying while holding
+    ///it, so at most one of them is escalated at a time and the rest park instead of
+    ///spinning. With it the buffer is no longer lock free: a holder that gets preempted
+    ///blocks every other operation until it runs again
+    #[cfg(feature = "hybrid")]
+    slow_path: Mutex<()>,
+    ///Set while an operation holds `slow_path`. Every other CAS on head or tail waits for
+    ///it to clear, so past the few CASes already in flight the holder's CAS wins
+    #[cfg(feature = "hybrid")]
+    escalated: Padded<AtomicBool>,
+    ///Operations that fell back to `slow_path`, for tests
+    #[cfg(all(test, feature = "hybrid"))]
+    slow_path_entries: AtomicUsize,
+    ///Producers parked in `push_async`, woken by pops
+    #[cfg(feature = "async")]
+    push_wakers: crate::wakers::WakerSet,
+    ///None only for the buffer backing another buffer's log
+    #[cfg(feature = "record")]
+    op_log: Option<OpLog>,
 }
 
 unsafe impl<T: Send, const N: usize> Sync for AtomicRingBufferMpmc<T, N> {}
 unsafe impl<T: Send, const N: usize> Send for AtomicRingBufferMpmc<T, N> {}
 
 impl<T, const N: usize> AtomicRingBufferMpmc<T, N> {
+    ///Identifies the synchronization used, handy for labelling metrics
+    pub const KIND: &str = "atomic-mpmc";
+
+    ///Masking a position with this wraps it onto its slot, see `AtomicRingBufferSpsc::MASK`
+    const MASK: usize = N - 1;
+
     pub fn new() -> Arc<Self> {
+        let this = Self::build();
+        #[cfg(feature = "record")]
+        let this = this.with_op_log();
+        Arc::new(this)
+    }
+
+    ///A buffer that does not log its own operations, the log itself is one
+    #[cfg(feature = "record")]
+    pub(crate) fn new_unrecorded() -> Arc<Self> {
+        Arc::new(Self::build())
+    }
+
+    #[cfg(feature = "record")]
+    fn with_op_log(mut self) -> Self {
+        self.op_log = Some(OpLog::new());
+        self
+    }
+
+    fn build() -> Self {
         const { assert!(N != 0 && N.is_power_of_two()) };
 
-        let buffer = std::array::from_fn(|i| Slot {
+        let buffer = core::array::from_fn(|i| Slot {
             sequence: AtomicUsize::new(i),
             data: UnsafeCell::new(MaybeUninit::uninit()),
         });
 
-        Arc::new(Self {
+        Self {
             head: Padded(AtomicUsize::new(0)),
             tail: Padded(AtomicUsize::new(0)),
             buffer,
-        })
+            #[cfg(feature = "std")]
+            drop_observer: OnceLock::new(),
+            #[cfg(feature = "metrics")]
+            metrics: Padded(Default::default()),
+            #[cfg(feature = "hybrid")]
+            slow_path: Mutex::new(()),
+            #[cfg(feature = "hybrid")]
+            escalated: Padded(AtomicBool::new(false)),
+            #[cfg(all(test, feature = "hybrid"))]
+            slow_path_entries: AtomicUsize::new(0),
+            #[cfg(feature = "async")]
+            push_wakers: Default::default(),
+            #[cfg(feature = "record")]
+            op_log: None,
+        }
+    }
+
+    ///Initializes a buffer at `ptr` without allocating, for arenas or shared memory.
+    ///With `record` the buffer gets no operation log since that would allocate, so
+    ///`dump_log` always returns nothing for it.
+    ///
+    ///# Safety
+    ///
+    ///- `ptr` must be non null, aligned to `align_of::<Self>()` (at least 64) and valid for
+    ///  writes of `size_of::<Self>()` bytes.
+    ///- Whatever was at `ptr` is overwritten without being dropped.
+    ///- The memory must stay valid and unmoved for as long as the buffer is used, and the
+    ///  caller is responsible for running its destructor (`ptr::drop_in_place`) exactly once
+    ///  if buffered items need dropping.
+    pub unsafe fn new_in_place(ptr: *mut Self) {
+        const { assert!(N != 0 && N.is_power_of_two()) };
+
+        unsafe {
+            (&raw mut (*ptr).head).write(Padded(AtomicUsize::new(0)));
+            (&raw mut (*ptr).tail).write(Padded(AtomicUsize::new(0)));
+            #[cfg(feature = "std")]
+            (&raw mut (*ptr).drop_observer).write(OnceLock::new());
+            #[cfg(feature = "metrics")]
+            (&raw mut (*ptr).metrics).write(Padded(Default::default()));
+            let slots = &raw mut (*ptr).buffer as *mut Slot<T>;
+            for i in 0..N {
+                let slot = slots.add(i);
+                (&raw mut (*slot).sequence).write(AtomicUsize::new(i));
+            }
+            #[cfg(feature = "hybrid")]
+            (&raw mut (*ptr).slow_path).write(Mutex::new(()));
+            #[cfg(feature = "hybrid")]
+            (&raw mut (*ptr).escalated).write(Padded(AtomicBool::new(false)));
+            #[cfg(all(test, feature = "hybrid"))]
+            (&raw mut (*ptr).slow_path_entries).write(AtomicUsize::new(0));
+            #[cfg(feature = "async")]
+            (&raw mut (*ptr).push_wakers).write(Default::default());
+            #[cfg(feature = "record")]
+            (&raw mut (*ptr).op_log).write(None);
+        }
+    }
+
+    ///Registers a callback that sees every element still buffered when the buffer is
+    ///dropped, in FIFO order and right before each one is destroyed. Handy for logging
+    ///messages lost at shutdown. Only the first observer sticks, later calls drop `f`
+    ///and return false
+    #[cfg(feature = "std")]
+    pub fn set_drop_observer(&self, f: impl Fn(&T) + Send + Sync + 'static) -> bool {
+        self.drop_observer.set(DropObserver(Box::new(f))).is_ok()
+    }
+
+    pub fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    ///Fails with `TryPushError::Full` holding the value if there is no free slot.
+    ///
+    ///With the `hybrid` feature this can block: a push that keeps losing the CAS race
+    ///takes a lock, and while someone holds it every other push and pop waits for it
+    pub fn push(&self, value: T) -> Result<(), TryPushError<T>> {
+        self.push_inner(value).map_err(TryPushError::Full)
+    }
+
+    #[inline(always)]
+    fn push_inner(&self, value: T) -> Result<(), T> {
+        self.push_with_cas(value, Self::cas_position)
     }
 
-    pub fn push(&self, value: T) -> Result<(), T> {
+    ///`push_inner` with the CAS on head passed in, so tests can make it lose
+    #[inline(always)]
+    fn push_with_cas(
+        &self,
+        value: T,
+        mut cas: impl FnMut(&AtomicUsize, usize) -> Result<usize, usize>,
+    ) -> Result<(), T> {
         let mut backoff = Backoff::new();
-        let mut head = self.head.load(Ordering::Relaxed);
+        let mut head = self.head.load(RELAXED);
+        let mut retries = 0;
+        #[cfg(feature = "hybrid")]
+        let mut escalation = None;
 
         loop {
-            let idx = head & (N - 1);
+            let idx = head & Self::MASK;
             let slot;
             unsafe {
                 slot = self.buffer.get_unchecked(idx);
             }
-            let seq = slot.sequence.load(Ordering::Acquire);
+            let seq = slot.sequence.load(ACQUIRE);
 
             let diff = seq as isize - head as isize;
 
             if diff == 0 {
-                match self.head.compare_exchange_weak(
-                    head,
-                    head + 1,
-                    Ordering::Relaxed,
-                    Ordering::Relaxed,
-                ) {
+                #[cfg(feature = "hybrid")]
+                if escalation.is_none() && self.wait_out_escalation() {
+                    head = self.head.load(RELAXED);
+                    continue;
+                }
+                match cas(&self.head, head) {
                     Ok(_) => {
                         unsafe {
                             (*slot.data.get()).write(value);
                         }
-                        slot.sequence.store(head.wrapping_add(1), Ordering::Release);
+                        #[cfg(feature = "record")]
+                        self.record_op(Op::Push, head);
+                        slot.sequence.store(head.wrapping_add(1), RELEASE);
+                        with_metrics!(self.record_lag(
+                            head.wrapping_add(1).wrapping_sub(self.tail.load(RELAXED))
+                        ));
+                        trace_op!(kind = Self::KIND, occupancy = self.len(), "push");
+                        with_metrics!(self.metrics.record_occupancy(self.len(), N));
+                        self.record_cas_retries(retries);
                         return Ok(());
                     }
                     Err(real_head) => {
                         head = real_head;
+                        retries += 1;
+                        #[cfg(feature = "debug-checks")]
+                        assert!(
+                            retries < CAS_RETRY_LIMIT,
+                            "push retried its CAS {retries} times, likely livelocked"
+                        );
+                        #[cfg(feature = "hybrid")]
+                        if retries == HYBRID_CAS_LIMIT {
+                            escalation = Some(self.escalate());
+                        }
                     }
                 }
             } else if diff < 0 {
-                let new_head = self.head.load(Ordering::Relaxed);
+                let new_head = self.head.load(RELAXED);
                 if new_head != head {
                     head = new_head;
                     backoff.reset();
                     continue;
                 }
+                trace_op!(
+                    kind = Self::KIND,
+                    occupancy = N,
+                    "push rejected, buffer full"
+                );
                 return Err(value);
             } else {
-                head = self.head.load(Ordering::Relaxed);
+                head = self.head.load(RELAXED);
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    ///Tries `push` up to `max_attempts` times, backing off in between, and returns how many
+    ///attempts it took. A count above 1 means the buffer kept filling up under the caller,
+    ///handy for adapting to contention without turning on the `metrics` feature.
+    ///Gives the value back once the attempts run out
+    pub fn push_counting(&self, mut value: T, max_attempts: usize) -> Result<usize, T> {
+        let mut backoff = Backoff::new();
+        for attempt in 1..=max_attempts {
+            match self.push_inner(value) {
+                Ok(()) => return Ok(attempt),
+                Err(back) => value = back,
+            }
+            backoff.snooze();
+        }
+        Err(value)
+    }
+
+    ///Keeps retrying with backoff until there is room or `deadline` passes, then gives the
+    ///value back. The clock is only read every few attempts, so the push can overshoot the
+    ///deadline by a handful of backoff steps
+    #[cfg(feature = "std")]
+    pub fn push_deadline(&self, mut value: T, deadline: Instant) -> Result<(), T> {
+        let mut backoff = Backoff::new();
+        let mut attempts = 0;
+
+        loop {
+            match self.push_inner(value) {
+                Ok(()) => return Ok(()),
+                Err(back) => value = back,
+            }
+
+            attempts += 1;
+            if attempts % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(value);
+            }
+            backoff.snooze();
+        }
+    }
+
+    ///Pushes all items as one contiguous run: head is advanced past the whole batch with
+    ///a single CAS, so no other producer's items land in between. Slots are published in
+    ///order, so consumers may see a prefix of the batch but never a gap. Gives the items
+    ///back if there is not room for all of them, or right away if the batch is longer than N
+    pub fn push_batch(&self, items: Vec<T>) -> Result<(), PushBatchError<T>> {
+        let n = items.len();
+        if n == 0 {
+            return Ok(());
+        }
+        if n > N {
+            return Err(PushBatchError::TooLarge(items));
+        }
+
+        let mut backoff = Backoff::new();
+        let mut head = self.head.load(RELAXED);
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            if head.wrapping_add(n).wrapping_sub(tail) > N {
+                let new_head = self.head.load(RELAXED);
+                if new_head != head {
+                    head = new_head;
+                    continue;
+                }
+                return Err(PushBatchError::Full(items));
             }
 
+            #[cfg(feature = "hybrid")]
+            if self.wait_out_escalation() {
+                head = self.head.load(RELAXED);
+                continue;
+            }
+            match self
+                .head
+                .compare_exchange_weak(head, head.wrapping_add(n), RELAXED, RELAXED)
+            {
+                Ok(_) => break,
+                Err(real_head) => head = real_head,
+            }
             backoff.snooze();
         }
+
+        for (i, value) in items.into_iter().enumerate() {
+            let pos = head.wrapping_add(i);
+            let slot;
+            unsafe {
+                slot = self.buffer.get_unchecked(pos & Self::MASK);
+            }
+
+            //The slot is reserved, a consumer may still be finishing its read of it
+            let mut backoff = Backoff::new();
+            while slot.sequence.load(ACQUIRE) != pos {
+                backoff.snooze();
+            }
+
+            unsafe {
+                (*slot.data.get()).write(value);
+            }
+            #[cfg(feature = "record")]
+            self.record_op(Op::Push, pos);
+            slot.sequence.store(pos.wrapping_add(1), RELEASE);
+        }
+
+        Ok(())
+    }
+
+    ///Pushes `value`, waiting for a pop to make room if the buffer is full
+    #[cfg(feature = "async")]
+    pub async fn push_async(&self, value: T) {
+        let mut value = Some(value);
+        core::future::poll_fn(|cx| {
+            let item = value.take().expect("polled after completion");
+            let item = match self.push_inner(item) {
+                Ok(()) => return core::task::Poll::Ready(()),
+                Err(item) => item,
+            };
+
+            self.push_wakers.register(cx.waker());
+            //a pop may have made room before we registered
+            match self.push_inner(item) {
+                Ok(()) => core::task::Poll::Ready(()),
+                Err(item) => {
+                    value = Some(item);
+                    core::task::Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    ///Pops, yielding to the async runtime between attempts while the buffer is empty
+    ///instead of spinning on the executor thread. Gives up with None after a bounded
+    ///number of yields, meant for buffers that are only briefly empty.
+    #[cfg(feature = "async")]
+    pub async fn pop_cooperative(&self) -> Option<T> {
+        for _ in 0..crate::wakers::COOPERATIVE_RETRIES {
+            if let Some(value) = self.pop() {
+                return Some(value);
+            }
+            crate::wakers::yield_now().await;
+        }
+        self.pop()
     }
 
+    ///With the `hybrid` feature this can block, the same way as `push`
     pub fn pop(&self) -> Option<T> {
+        self.pop_with_cas(Self::cas_position)
+    }
+
+    ///`pop` with the CAS on tail passed in, so tests can make it lose
+    #[inline(always)]
+    fn pop_with_cas(
+        &self,
+        mut cas: impl FnMut(&AtomicUsize, usize) -> Result<usize, usize>,
+    ) -> Option<T> {
         let mut backoff = Backoff::new();
-        let mut tail = self.tail.load(Ordering::Relaxed);
+        let mut tail = self.tail.load(RELAXED);
+        let mut retries = 0;
+        #[cfg(feature = "hybrid")]
+        let mut escalation = None;
 
         loop {
-            let idx = tail & (N - 1);
+            let idx = tail & Self::MASK;
             let slot;
             unsafe {
                 slot = self.buffer.get_unchecked(idx);
             }
 
-            let seq = slot.sequence.load(Ordering::Acquire);
+            let seq = slot.sequence.load(ACQUIRE);
 
             let diff = seq as isize - (tail.wrapping_add(1) as isize);
 
             if diff == 0 {
-                match self.tail.compare_exchange_weak(
-                    tail,
-                    tail + 1,
-                    Ordering::Relaxed,
-                    Ordering::Relaxed,
-                ) {
+                #[cfg(feature = "hybrid")]
+                if escalation.is_none() && self.wait_out_escalation() {
+                    tail = self.tail.load(RELAXED);
+                    continue;
+                }
+                match cas(&self.tail, tail) {
                     Ok(_) => {
                         let value = unsafe { (*slot.data.get()).assume_init_read() };
 
-                        slot.sequence.store(tail.wrapping_add(N), Ordering::Release);
+                        #[cfg(feature = "record")]
+                        self.record_op(Op::Pop, tail);
+                        slot.sequence.store(tail.wrapping_add(N), RELEASE);
+                        trace_op!(kind = Self::KIND, occupancy = self.len(), "pop");
 
+                        with_metrics!(self.metrics.record_poll(true));
+                        with_metrics!(self.metrics.record_occupancy(self.len(), N));
+                        self.record_cas_retries(retries);
+                        #[cfg(feature = "async")]
+                        self.push_wakers.wake_all();
                         return Some(value);
                     }
                     Err(real_tail) => {
                         tail = real_tail;
+                        retries += 1;
+                        #[cfg(feature = "debug-checks")]
+                        assert!(
+                            retries < CAS_RETRY_LIMIT,
+                            "pop retried its CAS {retries} times, likely livelocked"
+                        );
+                        #[cfg(feature = "hybrid")]
+                        if retries == HYBRID_CAS_LIMIT {
+                            escalation = Some(self.escalate());
+                        }
                     }
                 }
             } else if diff < 0 {
+                with_metrics!(self.metrics.record_poll(false));
+                with_metrics!(self.metrics.record_occupancy(self.len(), N));
                 return None;
             } else {
-                tail = self.tail.load(Ordering::Relaxed);
+                tail = self.tail.load(RELAXED);
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    ///Takes everything buffered right now in one go, oldest first. The whole range is
+    ///claimed with a single CAS on tail, so other consumers see the buffer as empty
+    ///afterwards and never get any of these items. Items pushed while it runs are left
+    ///for the next call. Meant for consumers that flush periodically
+    pub fn take_all_atomic(&self) -> Vec<T> {
+        let mut tail = self.tail.load(RELAXED);
+        let head = loop {
+            //head is read after tail, so the claim never goes past what producers took
+            let head = self.head.load(RELAXED);
+            let len = head.wrapping_sub(tail);
+            if len == 0 {
+                return Vec::new();
+            }
+            if len > N {
+                //a stale head from before tail moved on, look again
+                tail = self.tail.load(RELAXED);
+                continue;
+            }
+            #[cfg(feature = "hybrid")]
+            if self.wait_out_escalation() {
+                tail = self.tail.load(RELAXED);
+                continue;
+            }
+            match self
+                .tail
+                .compare_exchange_weak(tail, head, RELAXED, RELAXED)
+            {
+                Ok(_) => break head,
+                Err(real_tail) => tail = real_tail,
+            }
+        };
+
+        let len = head.wrapping_sub(tail);
+        let mut out = Vec::with_capacity(len);
+        let mut backoff = Backoff::new();
+        for offset in 0..len {
+            let pos = tail.wrapping_add(offset);
+            let slot = unsafe { self.buffer.get_unchecked(pos & Self::MASK) };
+            //the slot is ours but its producer may still be writing it
+            while slot.sequence.load(ACQUIRE) != pos.wrapping_add(1) {
+                backoff.snooze();
+            }
+            backoff.reset();
+            out.push(unsafe { (*slot.data.get()).assume_init_read() });
+            #[cfg(feature = "record")]
+            self.record_op(Op::Pop, pos);
+            slot.sequence.store(pos.wrapping_add(N), RELEASE);
+        }
+        trace_op!(kind = Self::KIND, taken = len, "take all");
+        with_metrics!(self.metrics.record_occupancy(self.len(), N));
+        #[cfg(feature = "async")]
+        self.push_wakers.wake_all();
+        out
+    }
+
+    ///Share of pops that found the buffer empty. A ratio close to 1.0 means the consumer
+    ///mostly spins on an empty buffer and would be better off parking
+    #[cfg(feature = "metrics")]
+    pub fn empty_poll_ratio(&self) -> f64 {
+        self.metrics.empty_poll_ratio()
+    }
+
+    ///Most CAS retries a single push or pop needed so far, a high number points at
+    ///pathological contention
+    #[cfg(feature = "metrics")]
+    pub fn max_cas_retries(&self) -> usize {
+        self.metrics.max_cas_retries()
+    }
+
+    ///How contended pushes and pops were over roughly the last 64 of them, from a moving
+    ///average of CAS retries per operation. A producer seeing `High` can switch to
+    ///`push_batch` to claim many slots per CAS
+    #[cfg(feature = "metrics")]
+    pub fn contention_level(&self) -> crate::ContentionLevel {
+        self.metrics.contention_level()
+    }
+
+    ///True when the buffer sat above 90% full on average over roughly the last 64 pushes
+    ///and pops, a sign it is too small for the load and will start rejecting pushes.
+    ///Clears again once the consumer catches up
+    #[cfg(feature = "metrics")]
+    pub fn is_chronically_full(&self) -> bool {
+        self.metrics.is_chronically_full()
+    }
+
+    ///All metrics counters read in one go with relaxed loads, so they may be slightly
+    ///out of step with each other while the buffer is in use
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> crate::Metrics {
+        self.metrics.snapshot()
+    }
+
+    ///Zeroes every metrics counter, returning the values they had right before.
+    ///Each counter is swapped on its own, an operation racing with the reset is counted
+    ///either in the returned values or after the reset, never in both
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&self) -> crate::Metrics {
+        self.metrics.reset()
+    }
+
+    ///Takes the recorded pushes and pops out of the operation log, oldest first.
+    ///Each operation is logged right before it is published, so a pop never shows up
+    ///ahead of the push it took. The log keeps the most recent 1024 operations
+    #[cfg(feature = "record")]
+    pub fn dump_log(&self) -> Vec<OpRecord> {
+        self.op_log.as_ref().map(OpLog::dump).unwrap_or_default()
+    }
+
+    #[cfg(feature = "record")]
+    #[inline]
+    fn record_op(&self, op: Op, seq: usize) {
+        if let Some(op_log) = &self.op_log {
+            op_log.record(op, seq);
+        }
+    }
+
+    #[inline(always)]
+    fn record_cas_retries(&self, _retries: usize) {
+        with_metrics!(self.metrics.record_cas_retries(_retries));
+    }
+
+    ///Takes the slow path and tells the fast path to stand aside until it is dropped
+    #[cfg(feature = "hybrid")]
+    fn escalate(&self) -> Escalation<'_> {
+        let lock = self.slow_path.lock();
+        self.escalated.store(true, RELAXED);
+        #[cfg(test)]
+        self.slow_path_entries.fetch_add(1, RELAXED);
+        Escalation {
+            escalated: &self.escalated,
+            _lock: lock,
+        }
+    }
+
+    ///Backs off while an escalated operation is running, returns whether it had to wait.
+    ///Callers reload their position afterwards, the one they had is likely stale
+    #[cfg(feature = "hybrid")]
+    #[inline(always)]
+    fn wait_out_escalation(&self) -> bool {
+        if !self.escalated.load(RELAXED) {
+            return false;
+        }
+        let mut backoff = Backoff::new();
+        while self.escalated.load(RELAXED) {
+            backoff.snooze();
+        }
+        true
+    }
+
+    ///Moves `position`, head or tail, one past `current`
+    #[inline(always)]
+    fn cas_position(position: &AtomicUsize, current: usize) -> Result<usize, usize> {
+        position.compare_exchange_weak(current, current + 1, RELAXED, RELAXED)
+    }
+
+    ///Pops an item, transforms it with `f` and pushes the new item straight back,
+    ///returning `f`'s result. This is best effort, not atomic: other consumers can pop in
+    ///between and the new item goes to the back of the queue. If producers fill the freed
+    ///slot first, it backs off until there is room again
+    pub fn pop_push<R>(&self, f: impl FnOnce(T) -> (T, R)) -> Option<R> {
+        let (mut item, result) = f(self.pop()?);
+
+        let mut backoff = Backoff::new();
+        while let Err(back) = self.push_inner(item) {
+            item = back;
+            backoff.snooze();
+        }
+
+        Some(result)
+    }
+
+    ///Returns a copy of the next item a consumer would pop, without popping it.
+    ///The slot is read seqlock style, so the copy is never torn, but a racing
+    ///consumer may pop the same item right after this returns!
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        let mut backoff = Backoff::new();
+
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let slot;
+            unsafe {
+                slot = self.buffer.get_unchecked(tail & Self::MASK);
+            }
+
+            let seq = slot.sequence.load(ACQUIRE);
+            if seq != tail.wrapping_add(1) {
+                if self.tail.load(RELAXED) == tail {
+                    return None;
+                }
+                backoff.snooze();
+                continue;
+            }
+
+            let value = unsafe { core::ptr::read_volatile(slot.data.get()) };
+
+            fence(ACQUIRE);
+            if slot.sequence.load(RELAXED) == seq {
+                return Some(unsafe { value.assume_init() });
             }
 
             backoff.snooze();
         }
     }
+
+    ///How far the consumer is behind the producer, the same as `len`
+    pub fn lag(&self) -> usize {
+        self.len()
+    }
+
+    ///Highest lag seen by a push since creation, the last `reset_lag_stats` or the last
+    ///`reset_metrics`
+    #[cfg(feature = "metrics")]
+    pub fn max_lag_since_reset(&self) -> usize {
+        self.metrics.max_lag()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn reset_lag_stats(&self) {
+        self.metrics.reset_lag();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[inline]
+    fn record_lag(&self, lag: usize) {
+        self.metrics.record_lag(lag, N);
+    }
+
+    ///Best effort copy of the buffered items, oldest first, appended to `out`. Meant for
+    ///cheap dashboards: it does not synchronize with producers or consumers, so while
+    ///they are running the copy may be stale, skip or repeat items. Slots that are not
+    ///published, or get popped while they are copied, are skipped, so a torn value is
+    ///never returned. Do not use it for anything correctness sensitive.
+    ///On a quiescent buffer it is exact
+    pub fn snapshot_lossy(&self, out: &mut Vec<T>)
+    where
+        T: Copy,
+    {
+        let tail = self.tail.load(RELAXED);
+        let head = self.head.load(RELAXED);
+        let len = head.wrapping_sub(tail).min(N);
+
+        out.reserve(len);
+        for offset in 0..len {
+            let pos = tail.wrapping_add(offset);
+            let slot = &self.buffer[pos & Self::MASK];
+            if let Some(value) = Self::read_published(slot, pos) {
+                out.push(value);
+            }
+        }
+    }
+
+    ///Every physical slot in storage order with its sequence number and, if occupied, a
+    ///copy of its item. For visualizing how the sequence numbers drive the buffer.
+    ///Best effort like `snapshot_lossy`: exact on a quiescent buffer, while others are
+    ///pushing and popping slots may be read at different moments, and a slot popped
+    ///while it is copied shows up without its value
+    pub fn slot_states(&self) -> Vec<SlotState<T>>
+    where
+        T: Copy,
+    {
+        let tail = self.tail.load(RELAXED);
+        self.buffer
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| {
+                //the position this slot stands for in the window starting at tail
+                let pos = tail.wrapping_add(index.wrapping_sub(tail) & Self::MASK);
+                let sequence = slot.sequence.load(ACQUIRE);
+                let value = Self::read_published(slot, pos);
+                SlotState {
+                    sequence,
+                    occupied: sequence == pos.wrapping_add(1),
+                    value,
+                }
+            })
+            .collect()
+    }
+
+    ///Copies the item pushed to `pos` out of its slot without claiming it. The sequence
+    ///is checked again after the copy: a consumer that popped the slot meanwhile lets a
+    ///producer overwrite it, so then the possibly torn copy is thrown away
+    fn read_published(slot: &Slot<T>, pos: usize) -> Option<T>
+    where
+        T: Copy,
+    {
+        if slot.sequence.load(ACQUIRE) != pos.wrapping_add(1) {
+            return None;
+        }
+        let value = unsafe { core::ptr::read_volatile(slot.data.get()) };
+        fence(ACQUIRE);
+        if slot.sequence.load(RELAXED) != pos.wrapping_add(1) {
+            return None;
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    ///Pushes default items until `level` are buffered, for benchmark and test setup.
+    ///Not meant to race with other producers
+    #[doc(hidden)]
+    pub fn fill_to(&self, level: usize)
+    where
+        T: Default,
+    {
+        crate::fill_with_default(N, level, self.len(), |value| self.push(value));
+    }
+
+    ///How many full laps the consumer has made around the storage, total throughput in
+    ///units of whole buffers. Wraps along with the raw tail counter after `usize::MAX` pops
+    pub fn wrap_count(&self) -> usize {
+        self.tail.load(ACQUIRE) / N
+    }
+
+    ///Number of buffered items. Tail is re-read until it is stable around the head load,
+    ///so the snapshot is consistent and never exceeds N
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let head = self.head.load(ACQUIRE);
+            if self.tail.load(ACQUIRE) == tail {
+                return head.wrapping_sub(tail);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Best effort: with other threads pushing and popping the answer can be stale by the
+    ///time it is used, so a push after `!is_full()` may still fail. Good enough for
+    ///admission control before building an expensive `T`
+    pub fn is_full(&self) -> bool {
+        self.len() >= N
+    }
+
+    ///Whether there is a free slot right now. Advisory like `is_full`, another producer
+    ///can take the slot before this one pushes
+    pub fn would_accept(&self) -> bool {
+        !self.is_full()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    ///How full the buffer is, from 0.0 (empty) to 1.0 (full)
+    pub fn fill_ratio(&self) -> f64 {
+        self.len() as f64 / N as f64
+    }
+
+    ///Looks for the reason `pop` keeps returning None while `len` says items are buffered:
+    ///the first slot between tail and head whose sequence does not say it was published.
+    ///A producer that is in the middle of a push looks the same for a moment, so only a
+    ///report that keeps coming back points at a real bug
+    #[cfg(feature = "debug-checks")]
+    pub fn diagnose_stall(&self) -> Option<StallReport> {
+        let tail = self.tail.load(ACQUIRE);
+        let head = self.head.load(ACQUIRE);
+        let len = head.wrapping_sub(tail).min(N);
+
+        (0..len).find_map(|offset| {
+            let position = tail.wrapping_add(offset);
+            let slot = position & Self::MASK;
+            let sequence = self.buffer[slot].sequence.load(ACQUIRE);
+            let expected_sequence = position.wrapping_add(1);
+            (sequence != expected_sequence).then_some(StallReport {
+                slot,
+                position,
+                expected_sequence,
+                sequence,
+            })
+        })
+    }
+
+    ///Panics if the buffer's invariants are broken: head is at most N ahead of tail,
+    ///every slot in `[tail, head)` is published with sequence `pos + 1` and every free
+    ///slot waits for its next lap with sequence `pos`. Only meaningful while no push or
+    ///pop is running, meant for tests and fuzzers
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn check_invariants(&self) {
+        let head = self.head.load(ACQUIRE);
+        let tail = self.tail.load(ACQUIRE);
+        let len = head.wrapping_sub(tail);
+        assert!(
+            len <= N,
+            "head {head} and tail {tail} are {len} apart, more than N = {N}"
+        );
+
+        for offset in 0..N {
+            let pos = tail.wrapping_add(offset);
+            let seq = self.buffer[pos & Self::MASK].sequence.load(ACQUIRE);
+            let expected = if offset < len {
+                pos.wrapping_add(1)
+            } else {
+                pos
+            };
+            assert_eq!(
+                seq,
+                expected,
+                "slot {} holds sequence {seq}, expected {expected} for position {pos}",
+                pos & Self::MASK
+            );
+        }
+    }
+
+    ///Drops everything buffered. Needs the buffer uniquely owned, e.g. through
+    ///`Arc::get_mut`, so no push or pop can be running
+    pub fn clear(&mut self) {
+        let head = *self.head.get_mut();
+        let mut tail = *self.tail.get_mut();
+
+        while tail != head {
+            let slot = &mut self.buffer[tail & Self::MASK];
+            let occupied = *slot.sequence.get_mut() == tail.wrapping_add(1);
+            if occupied {
+                unsafe { slot.data.get_mut().assume_init_drop() };
+            }
+            //as if popped, the slot is free for the push one lap later
+            *slot.sequence.get_mut() = tail.wrapping_add(N);
+            #[cfg(feature = "record")]
+            if occupied {
+                self.record_op(Op::Pop, tail);
+            }
+            tail = tail.wrapping_add(1);
+        }
+        *self.tail.get_mut() = head;
+    }
+
+    ///Head, tail and the values derived from them, from a single consistent read
+    pub fn geometry(&self) -> Geometry {
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let head = self.head.load(ACQUIRE);
+            if self.tail.load(ACQUIRE) == tail {
+                return Geometry::new(Self::MASK, head, tail);
+            }
+        }
+    }
+
     pub fn read_head(&self) -> usize {
-        self.head.load(Ordering::Acquire) % N
+        self.head.load(ACQUIRE) & Self::MASK
     }
 
     pub fn read_tail(&self) -> usize {
-        self.tail.load(Ordering::Acquire) % N
+        self.tail.load(ACQUIRE) & Self::MASK
     }
 
+    ///Whether slot `index` holds an item. The slot at the tail is the next one to pop
+    ///so it counts, the slot at the head is the next one to write so it doesn't
     pub fn exists(&self, index: usize) -> bool {
-        let mut tail = self.tail.load(Ordering::Acquire);
-        let mut head = self.head.load(Ordering::Acquire);
+        let mut tail = self.tail.load(ACQUIRE);
+        let mut head = self.head.load(ACQUIRE);
         if head == tail {
             return false;
         }
-        head &= N - 1;
-        tail &= N - 1;
+        head &= Self::MASK;
+        tail &= Self::MASK;
         if head > tail {
             head > index && index >= tail
         } else {
@@ -153,24 +984,31 @@ impl<T, const N: usize> AtomicRingBufferMpmc<T, N> {
 
 impl<T, const N: usize> Drop for AtomicRingBufferMpmc<T, N> {
     fn drop(&mut self) {
-        if !std::mem::needs_drop::<T>() {
+        #[cfg(feature = "std")]
+        let observer = self.drop_observer.take();
+        #[cfg(not(feature = "std"))]
+        let observer: Option<DropObserver<T>> = None;
+        if !core::mem::needs_drop::<T>() && observer.is_none() {
             return;
         }
 
-        let head = self.head.load(Ordering::Relaxed);
-        let mut tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(RELAXED);
+        let mut tail = self.tail.load(RELAXED);
 
         while tail != head {
-            let idx = tail & (N - 1);
+            let idx = tail & Self::MASK;
             let slot = &self.buffer[idx];
 
-            let seq = slot.sequence.load(Ordering::Relaxed);
+            let seq = slot.sequence.load(RELAXED);
             let expected_seq = tail.wrapping_add(1);
 
             if seq == expected_seq {
                 unsafe {
+                    if let Some(observer) = &observer {
+                        (observer.0)((*slot.data.get()).assume_init_ref());
+                    }
                     let raw_ptr = (*slot.data.get()).as_mut_ptr();
-                    std::ptr::drop_in_place(raw_ptr);
+                    core::ptr::drop_in_place(raw_ptr);
                 }
             }
 
@@ -186,6 +1024,13 @@ mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::thread;
 
+    #[test]
+    fn test_kind() {
+        let buffer = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(buffer.kind(), "atomic-mpmc");
+        assert_eq!(AtomicRingBufferMpmc::<i32, 4>::KIND, "atomic-mpmc");
+    }
+
     #[test]
     fn test_basic_push_and_read() {
         let queue: Arc<AtomicRingBufferMpmc<i32, 4>> = AtomicRingBufferMpmc::new();
@@ -208,7 +1053,7 @@ mod tests {
         assert!(queue.push(20).is_ok());
 
         let result = queue.push(30);
-        assert_eq!(result, Err(30));
+        assert_eq!(result, Err(TryPushError::Full(30)));
 
         assert_eq!(queue.pop(), Some(10));
 
@@ -289,34 +1134,1035 @@ mod tests {
             "Total items consumed must match total items produced"
         );
     }
-    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    #[test]
+    fn test_peek() {
+        let queue: Arc<AtomicRingBufferMpmc<i32, 4>> = AtomicRingBufferMpmc::new();
 
-    #[derive(Debug)]
-    struct DropTracker;
+        assert_eq!(queue.peek(), None);
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
 
-    impl Drop for DropTracker {
-        fn drop(&mut self) {
-            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
-        }
+        assert_eq!(queue.peek(), Some(1));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.peek(), Some(2));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.peek(), None);
     }
 
     #[test]
-    fn test_drop_cleanup() {
-        DROP_COUNTER.store(0, Ordering::Relaxed);
+    fn test_peek_never_torn() {
+        #[derive(Clone, Copy)]
+        struct Wide {
+            a: usize,
+            b: usize,
+            c: usize,
+            d: usize,
+        }
 
-        {
-            let buffer = AtomicRingBufferMpmc::<DropTracker, 8>::new();
+        const OPS: usize = 5_000;
 
-            for _ in 0..5 {
-                buffer.push(DropTracker).unwrap();
-            }
+        let queue: Arc<AtomicRingBufferMpmc<Wide, 8>> = AtomicRingBufferMpmc::new();
+        let done = Arc::new(AtomicUsize::new(0));
 
-            buffer.pop();
-            buffer.pop();
+        let producer = {
+            let q = queue.clone();
+            thread::spawn(move || {
+                for i in 0..OPS {
+                    let value = Wide {
+                        a: i,
+                        b: i,
+                        c: i,
+                        d: i,
+                    };
+                    while q.push(value).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let consumer = {
+            let q = queue.clone();
+            let d = done.clone();
+            thread::spawn(move || {
+                let mut count = 0;
+                while count < OPS {
+                    if q.pop().is_some() {
+                        count += 1;
+                    } else {
+                        std::thread::yield_now();
+                    }
+                }
+                d.store(1, Ordering::Relaxed);
+            })
+        };
+
+        while done.load(Ordering::Relaxed) == 0 {
+            if let Some(value) = queue.peek() {
+                assert!(
+                    value.a == value.b && value.b == value.c && value.c == value.d,
+                    "peek returned a torn value"
+                );
+            }
+            std::thread::yield_now();
+        }
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hybrid_extreme_contention() {
+        const NUM_THREADS: usize = 8;
+        const OPS_PER_THREAD: usize = 2_000;
+
+        let queue: Arc<AtomicRingBufferMpmc<usize, 2>> = AtomicRingBufferMpmc::new();
+        let barrier = Arc::new(Barrier::new(NUM_THREADS * 2));
+        let popped = Arc::new(AtomicUsize::new(0));
+        let sum = Arc::new(AtomicUsize::new(0));
+
+        //thread 0 on each side escalates every operation, they must all finish while the
+        //rest hammer the fast path
+        let mut handles = vec![];
+        for t in 0..NUM_THREADS {
+            let q = queue.clone();
+            let b = barrier.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+                for i in 0..OPS_PER_THREAD {
+                    loop {
+                        let pushed = if t == 0 {
+                            q.push_with_cas(i, losing_cas(HYBRID_CAS_LIMIT)).is_ok()
+                        } else {
+                            q.push(i).is_ok()
+                        };
+                        if pushed {
+                            break;
+                        }
+                        std::thread::yield_now();
+                    }
+                }
+            }));
+        }
+        for t in 0..NUM_THREADS {
+            let q = queue.clone();
+            let b = barrier.clone();
+            let p = popped.clone();
+            let s = sum.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+                while p.load(Ordering::Relaxed) < NUM_THREADS * OPS_PER_THREAD {
+                    let value = if t == 0 {
+                        q.pop_with_cas(losing_cas(HYBRID_CAS_LIMIT))
+                    } else {
+                        q.pop()
+                    };
+                    match value {
+                        Some(value) => {
+                            s.fetch_add(value, Ordering::Relaxed);
+                            p.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => std::thread::yield_now(),
+                    }
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(popped.load(Ordering::Relaxed), NUM_THREADS * OPS_PER_THREAD);
+        assert_eq!(
+            sum.load(Ordering::Relaxed),
+            NUM_THREADS * (OPS_PER_THREAD * (OPS_PER_THREAD - 1) / 2)
+        );
+        //every escalated push went through the slow path, plus some of the pops
+        assert!(queue.slow_path_entries.load(Ordering::Relaxed) >= OPS_PER_THREAD);
+        assert!(queue.slow_path.try_lock().is_some());
+    }
+
+    ///A CAS that loses its first `lost` attempts, as if another thread got there first
+    #[cfg(feature = "hybrid")]
+    fn losing_cas(mut lost: usize) -> impl FnMut(&AtomicUsize, usize) -> Result<usize, usize> {
+        move |position, current| {
+            if lost > 0 {
+                lost -= 1;
+                return Err(current);
+            }
+            AtomicRingBufferMpmc::<usize, 4>::cas_position(position, current)
+        }
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hybrid_falls_back_after_cas_limit() {
+        let queue = AtomicRingBufferMpmc::<usize, 4>::new();
+
+        //losing one CAS short of the limit stays on the fast path
+        queue
+            .push_with_cas(1, losing_cas(HYBRID_CAS_LIMIT - 1))
+            .unwrap();
+        assert_eq!(queue.slow_path_entries.load(Ordering::Relaxed), 0);
+
+        queue
+            .push_with_cas(2, losing_cas(HYBRID_CAS_LIMIT))
+            .unwrap();
+        assert_eq!(queue.slow_path_entries.load(Ordering::Relaxed), 1);
+
+        assert_eq!(
+            queue.pop_with_cas(losing_cas(HYBRID_CAS_LIMIT + 10)),
+            Some(1)
+        );
+        assert_eq!(queue.slow_path_entries.load(Ordering::Relaxed), 2);
+
+        //the lock is released with the operation
+        assert!(queue.slow_path.try_lock().is_some());
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.slow_path_entries.load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hybrid_fast_path_waits_for_escalation() {
+        let queue = AtomicRingBufferMpmc::<usize, 4>::new();
+        queue.push(1).unwrap();
+
+        let escalation = queue.escalate();
+        let handles: Vec<_> = [0, 1]
+            .map(|t| {
+                let q = queue.clone();
+                thread::spawn(move || {
+                    if t == 0 {
+                        q.push(2).unwrap();
+                        None
+                    } else {
+                        q.pop()
+                    }
+                })
+            })
+            .into();
+
+        //neither may CAS while the escalated operation runs
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(queue.read_head(), 1);
+        assert_eq!(queue.read_tail(), 0);
+
+        drop(escalation);
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results[1], Some(1));
+        assert_eq!(queue.pop(), Some(2));
+    }
+
+    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct DropTracker;
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_drop_cleanup() {
+        DROP_COUNTER.store(0, Ordering::Relaxed);
+
+        {
+            let buffer = AtomicRingBufferMpmc::<DropTracker, 8>::new();
+
+            for _ in 0..5 {
+                buffer.push(DropTracker).unwrap();
+            }
+
+            buffer.pop();
+            buffer.pop();
 
             assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 2);
         }
 
         assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 5);
     }
+
+    #[test]
+    fn test_fill_ratio() {
+        let buffer = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(buffer.fill_ratio(), 0.0);
+        assert!(buffer.is_empty());
+
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.fill_ratio(), 0.5);
+
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.fill_ratio(), 1.0);
+
+        buffer.pop();
+        assert_eq!(buffer.fill_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_new_in_place() {
+        let mut storage = Box::new(MaybeUninit::<AtomicRingBufferMpmc<String, 4>>::uninit());
+        let queue = unsafe {
+            AtomicRingBufferMpmc::new_in_place(storage.as_mut_ptr());
+            storage.assume_init()
+        };
+
+        assert!(queue.push("a".to_string()).is_ok());
+        assert!(queue.push("b".to_string()).is_ok());
+        assert_eq!(queue.pop().as_deref(), Some("a"));
+        assert_eq!(queue.len(), 1);
+
+        for i in 0..10 {
+            queue.push(i.to_string()).unwrap();
+            queue.pop().unwrap();
+        }
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[cfg(feature = "test-alloc")]
+    #[test]
+    fn test_new_in_place_does_not_allocate() {
+        use crate::test_alloc::allocations_during;
+
+        let mut storage = Box::new(MaybeUninit::<AtomicRingBufferMpmc<u64, 4>>::uninit());
+        let allocations = allocations_during(|| unsafe {
+            AtomicRingBufferMpmc::new_in_place(storage.as_mut_ptr());
+        });
+        assert_eq!(allocations, 0);
+
+        let queue = unsafe { storage.assume_init() };
+        queue.push(1).unwrap();
+        assert_eq!(queue.pop(), Some(1));
+        //the log is left out rather than allocated
+        #[cfg(feature = "record")]
+        assert!(queue.dump_log().is_empty());
+    }
+
+    #[test]
+    fn test_lag() {
+        let buffer = AtomicRingBufferMpmc::<i32, 8>::new();
+        assert_eq!(buffer.lag(), 0);
+
+        for i in 0..5 {
+            buffer.push(i).unwrap();
+            assert_eq!(buffer.lag(), i as usize + 1);
+        }
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(buffer.lag(), 3);
+        while buffer.pop().is_some() {}
+        assert_eq!(buffer.lag(), 0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_max_lag() {
+        let buffer = AtomicRingBufferMpmc::<i32, 8>::new();
+        for i in 0..5 {
+            buffer.push(i).unwrap();
+        }
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(buffer.max_lag_since_reset(), 5);
+
+        buffer.reset_lag_stats();
+        assert_eq!(buffer.max_lag_since_reset(), 0);
+        buffer.push(5).unwrap();
+        assert_eq!(buffer.max_lag_since_reset(), 4);
+
+        while buffer.pop().is_some() {}
+        assert_eq!(buffer.lag(), 0);
+        assert_eq!(buffer.max_lag_since_reset(), 4);
+    }
+
+    #[test]
+    fn test_pop_push() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(queue.pop_push(|x| (x, ())), None);
+
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        queue.push(3).unwrap();
+        queue.push(4).unwrap();
+
+        assert_eq!(queue.pop_push(|x| (x * 10, x)), Some(1));
+        assert_eq!(queue.len(), 4);
+
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), Some(10));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_empty_poll_ratio() {
+        let buffer = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(buffer.empty_poll_ratio(), 0.0);
+
+        for i in 0..10 {
+            assert_eq!(buffer.pop(), None);
+            buffer.push(i).unwrap();
+            assert_eq!(buffer.pop(), Some(i));
+        }
+        assert_eq!(buffer.empty_poll_ratio(), 0.5);
+
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.empty_poll_ratio() > 0.5);
+    }
+
+    #[test]
+    fn test_take_all_atomic() {
+        let queue = AtomicRingBufferMpmc::<String, 4>::new();
+        assert!(queue.take_all_atomic().is_empty());
+
+        for word in ["a", "b", "c"] {
+            queue.push(word.to_string()).unwrap();
+        }
+        assert_eq!(queue.pop().as_deref(), Some("a"));
+        queue.push("d".to_string()).unwrap();
+        queue.push("e".to_string()).unwrap();
+
+        assert_eq!(queue.take_all_atomic(), ["b", "c", "d", "e"]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+
+        queue.push("f".to_string()).unwrap();
+        assert_eq!(queue.take_all_atomic(), ["f"]);
+    }
+
+    #[test]
+    fn test_take_all_atomic_while_pushing() {
+        const NUM_PRODUCERS: usize = 3;
+        const ITEMS: usize = 20_000;
+
+        let queue: Arc<AtomicRingBufferMpmc<usize, 64>> = AtomicRingBufferMpmc::new();
+
+        let mut handles = vec![];
+        for p_id in 0..NUM_PRODUCERS {
+            let q = queue.clone();
+            handles.push(thread::spawn(move || {
+                for i in 0..ITEMS {
+                    let mut value = p_id * ITEMS + i;
+                    while let Err(back) = q.push(value) {
+                        value = back.into_inner();
+                        std::thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        let total = NUM_PRODUCERS * ITEMS;
+        let mut received = Vec::with_capacity(total);
+        while received.len() < total {
+            let taken = queue.take_all_atomic();
+            if taken.is_empty() {
+                std::thread::yield_now();
+            }
+            received.extend(taken);
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        //every producer's items come out in the order it pushed them
+        for p_id in 0..NUM_PRODUCERS {
+            let own: Vec<usize> = received
+                .iter()
+                .copied()
+                .filter(|v| v / ITEMS == p_id)
+                .collect();
+            assert_eq!(own, (p_id * ITEMS..(p_id + 1) * ITEMS).collect::<Vec<_>>());
+        }
+        received.sort_unstable();
+        assert_eq!(received, (0..total).collect::<Vec<_>>());
+        assert!(queue.take_all_atomic().is_empty());
+    }
+
+    #[test]
+    fn test_push_batch() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+
+        assert!(queue.push_batch(vec![]).is_ok());
+        assert!(queue.push_batch(vec![1, 2, 3]).is_ok());
+        assert_eq!(
+            queue.push_batch(vec![4, 5]),
+            Err(PushBatchError::Full(vec![4, 5]))
+        );
+
+        assert_eq!(queue.pop(), Some(1));
+        assert!(queue.push_batch(vec![4, 5]).is_ok());
+        for i in 2..=5 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_push_batch_not_interleaved() {
+        const NUM_PRODUCERS: usize = 4;
+        const BATCHES: usize = 500;
+        const BATCH_LEN: usize = 4;
+
+        let queue: Arc<AtomicRingBufferMpmc<(usize, usize), 16>> = AtomicRingBufferMpmc::new();
+
+        let mut handles = vec![];
+        for p_id in 0..NUM_PRODUCERS {
+            let q = queue.clone();
+            handles.push(thread::spawn(move || {
+                for b in 0..BATCHES {
+                    let mut batch: Vec<_> =
+                        (0..BATCH_LEN).map(|i| (p_id, b * BATCH_LEN + i)).collect();
+                    while let Err(back) = q.push_batch(batch) {
+                        batch = back.into_inner();
+                        std::thread::yield_now();
+                    }
+                }
+            }));
+        }
+
+        let total = NUM_PRODUCERS * BATCHES * BATCH_LEN;
+        let mut received = Vec::with_capacity(total);
+        while received.len() < total {
+            match queue.pop() {
+                Some(item) => received.push(item),
+                None => std::thread::yield_now(),
+            }
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        for batch in received.chunks(BATCH_LEN) {
+            let (p_id, first) = batch[0];
+            assert_eq!(first % BATCH_LEN, 0, "batch does not start at a boundary");
+            for (i, item) in batch.iter().enumerate() {
+                assert_eq!(*item, (p_id, first + i), "batch was interleaved");
+            }
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_max_cas_retries() {
+        let queue: Arc<AtomicRingBufferMpmc<usize, 4>> = AtomicRingBufferMpmc::new();
+        for i in 0..100 {
+            queue.push(i).unwrap();
+            queue.pop().unwrap();
+        }
+        assert_eq!(queue.max_cas_retries(), 0);
+
+        //On a machine with few cores the threads rarely overlap, so keep hammering for a
+        //bounded number of rounds until some CAS actually loses. The `record` feature makes
+        //every operation spend most of its time in the op log, so give it more rounds
+        const NUM_THREADS: usize = 8;
+        const OPS_PER_THREAD: usize = 20_000;
+        const ROUNDS: usize = if cfg!(feature = "record") { 1000 } else { 100 };
+        for _ in 0..ROUNDS {
+            let barrier = Arc::new(Barrier::new(NUM_THREADS));
+            let mut handles = vec![];
+            for _ in 0..NUM_THREADS {
+                let q = queue.clone();
+                let b = barrier.clone();
+                handles.push(thread::spawn(move || {
+                    b.wait();
+                    for i in 0..OPS_PER_THREAD {
+                        if q.push(i).is_err() {
+                            q.pop();
+                        }
+                        q.pop();
+                    }
+                }));
+            }
+            for h in handles {
+                h.join().unwrap();
+            }
+            if queue.max_cas_retries() > 0 {
+                break;
+            }
+        }
+
+        assert!(queue.max_cas_retries() > 0);
+    }
+
+    #[test]
+    fn test_slot_states() {
+        let state = |sequence, value: Option<u32>| SlotState {
+            sequence,
+            occupied: value.is_some(),
+            value,
+        };
+
+        let queue = AtomicRingBufferMpmc::<u32, 4>::new();
+        let fresh: Vec<_> = (0..4).map(|i| state(i, None)).collect();
+        assert_eq!(queue.slot_states(), fresh);
+
+        for i in 1..=3 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(
+            queue.slot_states(),
+            [
+                state(4, None),
+                state(2, Some(2)),
+                state(3, Some(3)),
+                state(3, None)
+            ]
+        );
+
+        //wraps, slot 0 now holds position 4
+        queue.push(4).unwrap();
+        queue.push(5).unwrap();
+        assert_eq!(
+            queue.slot_states(),
+            [
+                state(5, Some(5)),
+                state(2, Some(2)),
+                state(3, Some(3)),
+                state(4, Some(4))
+            ]
+        );
+
+        while queue.pop().is_some() {}
+        //every slot waits for the next position that maps to it
+        assert_eq!(
+            queue.slot_states(),
+            [
+                state(8, None),
+                state(5, None),
+                state(6, None),
+                state(7, None)
+            ]
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_contention_level() {
+        use crate::ContentionLevel;
+
+        let queue: Arc<AtomicRingBufferMpmc<usize, 4>> = AtomicRingBufferMpmc::new();
+        assert_eq!(queue.contention_level(), ContentionLevel::Low);
+        for i in 0..1000 {
+            queue.push(i).unwrap();
+            queue.pop().unwrap();
+        }
+        assert_eq!(queue.contention_level(), ContentionLevel::Low);
+        //the Medium and High thresholds are tested on the counters alone in metrics.rs,
+        //threads on a machine with few cores can't be made to collide that often.
+        //`test_max_cas_retries` shows real retries do reach the counters
+    }
+
+    #[test]
+    fn test_push_batch_too_large() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+
+        assert_eq!(
+            queue.push_batch(vec![0; 5]),
+            Err(PushBatchError::TooLarge(vec![0; 5]))
+        );
+        assert!(queue.is_empty());
+
+        queue.push(1).unwrap();
+        assert_eq!(
+            queue.push_batch(vec![0; 8]),
+            Err(PushBatchError::TooLarge(vec![0; 8]))
+        );
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        queue.check_invariants();
+        for i in 0..10 {
+            queue.push(i).unwrap();
+            queue.check_invariants();
+            if i % 3 == 0 {
+                queue.pop();
+                queue.check_invariants();
+            }
+            if queue.len() == 4 {
+                queue.pop();
+            }
+        }
+        while queue.len() > 2 {
+            queue.pop();
+        }
+        queue.push_batch(vec![1, 2]).unwrap();
+        queue.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "holds sequence")]
+    fn test_check_invariants_catches_corruption() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        queue.buffer[1].sequence.store(7, Ordering::Relaxed);
+        queue.check_invariants();
+    }
+
+    #[test]
+    fn test_snapshot_lossy_quiescent() {
+        let queue = AtomicRingBufferMpmc::<u32, 4>::new();
+        let mut out = Vec::new();
+        queue.snapshot_lossy(&mut out);
+        assert!(out.is_empty());
+
+        for i in 0..4 {
+            queue.push(i).unwrap();
+        }
+        queue.pop();
+        queue.pop();
+        queue.push(4).unwrap();
+        queue.snapshot_lossy(&mut out);
+        assert_eq!(out, vec![2, 3, 4]);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn test_slot_states_never_torn() {
+        let queue = AtomicRingBufferMpmc::<[usize; 8], 4>::new();
+        let running = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let worker = thread::spawn({
+            let queue = queue.clone();
+            let running = running.clone();
+            move || {
+                let mut i = 0;
+                while running.load(Ordering::Relaxed) {
+                    let _ = queue.push([i; 8]);
+                    queue.pop();
+                    i += 1;
+                }
+            }
+        });
+
+        for _ in 0..10_000 {
+            //every word of an item is the same number, a torn copy would mix two items
+            for state in queue.slot_states() {
+                if let Some(item) = state.value {
+                    assert!(item.iter().all(|&word| word == item[0]));
+                }
+            }
+        }
+        running.store(false, Ordering::Relaxed);
+        worker.join().unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_push_async_waits_for_room() {
+        let queue = AtomicRingBufferMpmc::<i32, 2>::new();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+
+        let producer = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push_async(3).await }
+        });
+
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!producer.is_finished());
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop(), Some(1));
+        producer.await.unwrap();
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_snapshot_and_reset() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(queue.metrics_snapshot(), crate::Metrics::default());
+
+        queue.pop();
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        while queue.pop().is_some() {}
+
+        let snapshot = queue.metrics_snapshot();
+        assert_eq!(snapshot.empty_polls, 2);
+        assert_eq!(snapshot.hit_polls, 3);
+        assert_eq!(snapshot.max_cas_retries, 0);
+        assert_eq!(snapshot.max_lag, 3);
+        assert!(snapshot.occupancy_average > 0.0);
+        assert_eq!(snapshot.retry_average, 0.0);
+
+        assert_eq!(queue.reset_metrics(), snapshot);
+        assert_eq!(queue.metrics_snapshot(), crate::Metrics::default());
+        assert_eq!(queue.max_lag_since_reset(), 0);
+        assert!(!queue.is_chronically_full());
+        assert_eq!(queue.empty_poll_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_push_counting() {
+        let queue = AtomicRingBufferMpmc::<i32, 2>::new();
+        assert_eq!(queue.push_counting(1, 3), Ok(1));
+        assert_eq!(queue.push_counting(2, 3), Ok(1));
+        assert_eq!(queue.push_counting(3, 3), Err(3));
+        assert_eq!(queue.push_counting(3, 0), Err(3));
+
+        let consumer = thread::spawn({
+            let queue = queue.clone();
+            move || {
+                thread::sleep(std::time::Duration::from_millis(20));
+                queue.pop()
+            }
+        });
+        let attempts = queue.push_counting(3, usize::MAX).unwrap();
+        assert!(attempts > 1);
+        assert_eq!(consumer.join().unwrap(), Some(1));
+
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+    }
+
+    #[test]
+    fn test_push_counting_under_contention() {
+        const PRODUCERS: usize = 4;
+        const ITEMS: usize = 500;
+
+        let queue = AtomicRingBufferMpmc::<usize, 2>::new();
+        let mut producers = vec![];
+        for _ in 0..PRODUCERS {
+            let queue = queue.clone();
+            producers.push(thread::spawn(move || {
+                (0..ITEMS)
+                    .map(|i| queue.push_counting(i, usize::MAX).unwrap())
+                    .max()
+                    .unwrap()
+            }));
+        }
+
+        let mut popped = 0;
+        while popped < PRODUCERS * ITEMS {
+            if queue.pop().is_some() {
+                popped += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        let most = producers.into_iter().map(|p| p.join().unwrap()).max();
+        assert!(most.unwrap() > 1);
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    fn test_diagnose_stall() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        assert_eq!(queue.diagnose_stall(), None);
+        for i in 0..6 {
+            queue.push(i).unwrap();
+            queue.pop();
+        }
+        queue.push(6).unwrap();
+        queue.push(7).unwrap();
+        assert_eq!(queue.diagnose_stall(), None);
+
+        //tail is at position 6, slot 2
+        queue.buffer[2].sequence.store(3, Ordering::Relaxed);
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(
+            queue.diagnose_stall(),
+            Some(StallReport {
+                slot: 2,
+                position: 6,
+                expected_sequence: 7,
+                sequence: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_push_deadline_times_out() {
+        use std::time::Duration;
+
+        let queue = AtomicRingBufferMpmc::<String, 2>::new();
+        queue
+            .push_deadline("a".into(), Instant::now() + Duration::from_secs(1))
+            .unwrap();
+        queue.push("b".into()).unwrap();
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(50);
+        assert_eq!(queue.push_deadline("c".into(), deadline), Err("c".into()));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50));
+        assert!(elapsed < Duration::from_secs(1));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn test_dump_log() {
+        let queue = AtomicRingBufferMpmc::<i32, 4>::new();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        queue.pop();
+        queue.pop();
+        assert_eq!(queue.pop(), None);
+        queue.push(3).unwrap();
+        assert_eq!(queue.push_counting(4, 1), Ok(1));
+        queue.pop();
+        queue.pop();
+        //one record per item, not one per batch
+        queue.push_batch(vec![5, 6]).unwrap();
+
+        let main = thread::current().id();
+        let log = queue.dump_log();
+        let ops: Vec<(Op, usize)> = log.iter().map(|r| (r.op, r.seq)).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (Op::Push, 0),
+                (Op::Push, 1),
+                (Op::Pop, 0),
+                (Op::Pop, 1),
+                (Op::Push, 2),
+                (Op::Push, 3),
+                (Op::Pop, 2),
+                (Op::Pop, 3),
+                (Op::Push, 4),
+                (Op::Push, 5),
+            ]
+        );
+        assert!(log.iter().all(|r| r.thread == main));
+        assert!(queue.dump_log().is_empty());
+    }
+
+    #[test]
+    fn test_boxed_closures() {
+        type Job = Box<dyn FnOnce() -> usize + Send>;
+
+        let queue = AtomicRingBufferMpmc::<Job, 4>::new();
+        for i in 0..3 {
+            let name = format!("job {i}");
+            assert!(queue.push(Box::new(move || name.len() + i)).is_ok());
+        }
+
+        let results: Vec<usize> = std::iter::from_fn(|| queue.pop())
+            .map(|job| job())
+            .collect();
+        assert_eq!(results, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn test_undrained_boxed_closures_are_dropped() {
+        let capture = std::sync::Arc::new(());
+        {
+            let queue = AtomicRingBufferMpmc::<Box<dyn FnOnce() + Send>, 8>::new();
+            for _ in 0..5 {
+                let capture = capture.clone();
+                assert!(queue.push(Box::new(move || drop(capture))).is_ok());
+            }
+            (queue.pop().unwrap())();
+            assert_eq!(std::sync::Arc::strong_count(&capture), 5);
+        }
+        assert_eq!(std::sync::Arc::strong_count(&capture), 1);
+    }
+
+    #[cfg(feature = "test-alloc")]
+    #[test]
+    fn test_hot_path_does_not_allocate() {
+        use crate::test_alloc::allocations_during;
+
+        let queue = AtomicRingBufferMpmc::<u64, 8>::new();
+        let allocations = allocations_during(|| {
+            for round in 0..100 {
+                for i in 0..8 {
+                    queue.push(round * 8 + i).unwrap();
+                }
+                assert!(queue.push(0).is_err());
+                while queue.pop().is_some() {}
+                assert!(queue.pop().is_none());
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_is_full() {
+        let queue = AtomicRingBufferMpmc::<i32, 2>::new();
+        assert_eq!(queue.capacity(), 2);
+        assert!(!queue.is_full());
+        queue.push(1).unwrap();
+        assert!(!queue.is_full());
+        queue.push(2).unwrap();
+        assert!(queue.is_full());
+        queue.pop();
+        assert!(!queue.is_full());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_pop_cooperative_lets_other_tasks_run() {
+        let queue = AtomicRingBufferMpmc::<i32, 2>::new();
+        let producer = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                for _ in 0..5 {
+                    tokio::task::yield_now().await;
+                }
+                queue.push(7).unwrap();
+            }
+        });
+
+        //single threaded runtime, the producer only gets to push if we yield
+        assert_eq!(queue.pop_cooperative().await, Some(7));
+        producer.await.unwrap();
+        assert_eq!(queue.pop_cooperative().await, None);
+    }
+
+    #[test]
+    fn test_exists_counts_tail_slot() {
+        let buffer = AtomicRingBufferMpmc::<usize, 4>::new();
+        assert!((0..4).all(|i| !buffer.exists(i)));
+
+        buffer.push(0).unwrap();
+        buffer.push(1).unwrap();
+        buffer.pop();
+        let occupied: Vec<bool> = (0..4).map(|i| buffer.exists(i)).collect();
+        assert_eq!(occupied, [false, true, false, false]);
+
+        for i in 2..5 {
+            buffer.push(i).unwrap();
+        }
+        assert!((0..4).all(|i| buffer.exists(i)));
+
+        buffer.pop();
+        buffer.pop();
+        let occupied: Vec<bool> = (0..4).map(|i| buffer.exists(i)).collect();
+        assert_eq!(occupied, [true, false, false, true]);
+    }
+
+    #[test]
+    fn test_would_accept() {
+        let buffer = AtomicRingBufferMpmc::<usize, 4>::new();
+        assert!(buffer.would_accept());
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert!(!buffer.would_accept());
+        buffer.pop();
+        assert!(buffer.would_accept());
+    }
 }
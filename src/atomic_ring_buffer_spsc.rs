@@ -1,23 +1,98 @@
-use std::{
-    cell::UnsafeCell,
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
     mem::MaybeUninit,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicUsize, fence},
 };
 
-use crate::{Padded, primitives::Arc};
+#[cfg(feature = "timing")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "record")]
+use crate::op_log::{Op, OpLog, OpRecord};
+use crate::ordering::{ACQUIRE, RELAXED, RELEASE};
+use crate::{
+    AtomicRingBufferMpmc, DropObserver, Geometry, Padded, PopBuffer, TryPushError, primitives::Arc,
+};
+
+///The logical position an item was pushed to, the same on the push and the pop side
+///so individual items can be traced through the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ticket {
+    pub seq: usize,
+}
+
+///What happened to a value given to `push_adaptive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome<T> {
+    Accepted,
+    ///Dropped on purpose to smooth out the loss while above the high watermark
+    DroppedProactive(T),
+    ///Dropped because the buffer was full
+    DroppedFull(T),
+}
 
 ///Uses atomic's instead of mutexes
 #[derive(Debug)]
 pub struct AtomicRingBufferSpsc<T, const N: usize> {
     cached_head: UnsafeCell<usize>,
     cached_tail: UnsafeCell<usize>,
+    ///Cursor of `deliver`, everything between tail and it is delivered but not acked yet.
+    ///Only the consumer writes it, it is atomic so anyone can read `unacked`
+    delivered: AtomicUsize,
     head: Padded<AtomicUsize>,
     tail: Padded<AtomicUsize>,
+    ///`push_adaptive` starts dropping once more than this many items are buffered
+    high_watermark: AtomicUsize,
+    ///`push_adaptive` drops every this many'th push while above the watermark
+    drop_every: AtomicUsize,
+    ///Producer side count of pushes seen above the watermark since the last drop
+    above_watermark: UnsafeCell<usize>,
     buffer: UnsafeCell<[MaybeUninit<T>; N]>,
+    #[cfg(feature = "std")]
+    drop_observer: OnceLock<DropObserver<T>>,
+    #[cfg(feature = "metrics")]
+    metrics: Padded<crate::metrics::Counters>,
+    #[cfg(feature = "record")]
+    op_log: OpLog,
+    ///When each slot was last written, in nanoseconds since `epoch`
+    #[cfg(feature = "timing")]
+    stamps: UnsafeCell<[u64; N]>,
+    #[cfg(feature = "timing")]
+    epoch: Instant,
+    ///The consumer parked in `recv`, woken by pushes
+    #[cfg(feature = "async")]
+    pop_waker: crate::wakers::WakerSlot,
+    #[cfg(test)]
+    cache_refreshes: AtomicUsize,
 }
 unsafe impl<T, const N: usize> Sync for AtomicRingBufferSpsc<T, N> {}
 
 impl<T, const N: usize> AtomicRingBufferSpsc<T, N> {
+    ///Identifies the synchronization used, handy for labelling metrics
+    pub const KIND: &str = "atomic-spsc";
+
+    ///Masking a position with this wraps it onto its slot. A const so the subtraction is
+    ///done once at compile time: in release builds the mask is an immediate, e.g. the shm
+    ///push for N = 16 compiles to `andl $15, %edx` on x86_64 (checked with `--emit asm`)
+    const MASK: usize = N - 1;
+
+    ///Buffers with slots up to this many bytes are built on the stack and moved into their
+    ///allocation, bigger ones are written straight into it
+    const INLINE_LIMIT: usize = 64 * 1024;
+
+    ///`push_adaptive` defaults, dropping every 2nd push above 3/4 of the capacity
+    const DEFAULT_HIGH_WATERMARK: usize = N - N / 4;
+    const DEFAULT_DROP_EVERY: usize = 2;
+
+    ///Creates the buffer. The slots always live inline in the buffer's single allocation,
+    ///only how it gets there depends on its size: small buffers are built as a value and
+    ///moved to the heap, which is the cheapest way for them, while buffers over 64KiB of
+    ///slots are initialized in place on the heap so they can be larger than the stack
     pub fn new() -> Arc<Self> {
         const {
             assert!(
@@ -25,47 +100,415 @@ impl<T, const N: usize> AtomicRingBufferSpsc<T, N> {
                 "Buffer size N must be a power of two"
             )
         };
+        if N * size_of::<T>() <= Self::INLINE_LIMIT {
+            Self::new_inline()
+        } else {
+            Self::new_on_heap()
+        }
+    }
+
+    #[inline(never)]
+    fn new_inline() -> Arc<Self> {
         Arc::new(Self {
             cached_head: UnsafeCell::new(0),
             cached_tail: UnsafeCell::new(0),
-            buffer: UnsafeCell::new(std::array::from_fn(|_| MaybeUninit::uninit())),
+            delivered: AtomicUsize::new(0),
+            buffer: UnsafeCell::new(core::array::from_fn(|_| MaybeUninit::uninit())),
             head: Padded(AtomicUsize::new(0)),
             tail: Padded(AtomicUsize::new(0)),
+            high_watermark: AtomicUsize::new(Self::DEFAULT_HIGH_WATERMARK),
+            drop_every: AtomicUsize::new(Self::DEFAULT_DROP_EVERY),
+            above_watermark: UnsafeCell::new(0),
+            #[cfg(feature = "std")]
+            drop_observer: OnceLock::new(),
+            #[cfg(feature = "metrics")]
+            metrics: Padded(Default::default()),
+            #[cfg(feature = "record")]
+            op_log: OpLog::new(),
+            #[cfg(feature = "timing")]
+            stamps: UnsafeCell::new([0; N]),
+            #[cfg(feature = "timing")]
+            epoch: Instant::now(),
+            #[cfg(feature = "async")]
+            pop_waker: Default::default(),
+            #[cfg(test)]
+            cache_refreshes: AtomicUsize::new(0),
         })
     }
 
-    pub fn push(&self, value: T) -> Result<(), T> {
-        let head = self.head.load(Ordering::Relaxed);
+    #[inline(never)]
+    fn new_on_heap() -> Arc<Self> {
+        unsafe {
+            Arc::new_in_place(|ptr: *mut Self| {
+                (&raw mut (*ptr).cached_head).write(UnsafeCell::new(0));
+                (&raw mut (*ptr).cached_tail).write(UnsafeCell::new(0));
+                (&raw mut (*ptr).delivered).write(AtomicUsize::new(0));
+                (&raw mut (*ptr).head).write(Padded(AtomicUsize::new(0)));
+                (&raw mut (*ptr).tail).write(Padded(AtomicUsize::new(0)));
+                (&raw mut (*ptr).high_watermark)
+                    .write(AtomicUsize::new(Self::DEFAULT_HIGH_WATERMARK));
+                (&raw mut (*ptr).drop_every).write(AtomicUsize::new(Self::DEFAULT_DROP_EVERY));
+                (&raw mut (*ptr).above_watermark).write(UnsafeCell::new(0));
+                //the slots are MaybeUninit, nothing to write
+                #[cfg(feature = "std")]
+                (&raw mut (*ptr).drop_observer).write(OnceLock::new());
+                #[cfg(feature = "metrics")]
+                (&raw mut (*ptr).metrics).write(Padded(Default::default()));
+                #[cfg(feature = "record")]
+                (&raw mut (*ptr).op_log).write(OpLog::new());
+                #[cfg(feature = "timing")]
+                (&raw mut (*ptr).stamps).cast::<u64>().write_bytes(0, N);
+                #[cfg(feature = "timing")]
+                (&raw mut (*ptr).epoch).write(Instant::now());
+                #[cfg(feature = "async")]
+                (&raw mut (*ptr).pop_waker).write(Default::default());
+                #[cfg(test)]
+                (&raw mut (*ptr).cache_refreshes).write(AtomicUsize::new(0));
+            })
+        }
+    }
+
+    ///Registers a callback that sees every element still buffered when the buffer is
+    ///dropped, in FIFO order and right before each one is destroyed. Handy for logging
+    ///messages lost at shutdown. Only the first observer sticks, later calls drop `f`
+    ///and return false
+    #[cfg(feature = "std")]
+    pub fn set_drop_observer(&self, f: impl Fn(&T) + Send + Sync + 'static) -> bool {
+        self.drop_observer.set(DropObserver(Box::new(f))).is_ok()
+    }
+
+    pub fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    ///Fails with `TryPushError::Full` holding the value if there is no free slot
+    pub fn push(&self, value: T) -> Result<(), TryPushError<T>> {
+        self.push_seq(value).map(|_| ()).map_err(TryPushError::Full)
+    }
+
+    ///Like `push` but returns the ticket of the position the item went to
+    pub fn push_ticketed(&self, value: T) -> Result<Ticket, TryPushError<T>> {
+        self.push_seq(value)
+            .map(|seq| Ticket { seq })
+            .map_err(TryPushError::Full)
+    }
+
+    #[inline(always)]
+    fn push_seq(&self, value: T) -> Result<usize, T> {
+        let head = self.head.load(RELAXED);
         let mut tail;
         unsafe {
             tail = self.cached_tail.get().read();
         }
 
         if head.wrapping_sub(tail) == N {
-            tail = self.tail.load(Ordering::Acquire);
-
-            unsafe {
-                self.cached_tail.get().write(tail);
-            }
+            tail = self.refresh_cached_tail();
 
             if head.wrapping_sub(tail) == N {
+                trace_op!(
+                    kind = Self::KIND,
+                    occupancy = N,
+                    "push rejected, buffer full"
+                );
                 return Err(value);
             }
         }
 
         unsafe {
             let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
-            let slot_ptr = buffer_ptr.add(head & (N - 1));
+            let slot_ptr = buffer_ptr.add(head & Self::MASK);
             (*slot_ptr).write(value);
         }
 
-        self.head.store(head.wrapping_add(1), Ordering::Release);
+        #[cfg(feature = "record")]
+        self.op_log.record(Op::Push, head);
+        #[cfg(feature = "timing")]
+        self.stamp(head, 1);
+        self.head.store(head.wrapping_add(1), RELEASE);
+        self.wake_consumer();
+        with_metrics!(self.record_lag(head.wrapping_add(1).wrapping_sub(self.tail.load(RELAXED))));
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = head.wrapping_add(1).wrapping_sub(self.tail.load(RELAXED)),
+            "push"
+        );
+        with_metrics!(self.metrics.record_occupancy(self.len(), N));
 
-        Ok(())
+        Ok(head)
+    }
+
+    ///Push for lossy real-time streams that would rather shed load early than hit a wall.
+    ///While at most `high_watermark` items are buffered it behaves like `push`. Above it
+    ///every `drop_every`th push is dropped on purpose and comes back as
+    ///`DroppedProactive`, so when the producer outpaces the consumer the loss is spread
+    ///out evenly instead of every push failing at once when the buffer is full.
+    ///If the consumer keeps up with `1 - 1/drop_every` of the producer's rate the buffer
+    ///settles just above the watermark and never saturates, which keeps some headroom
+    ///for bursts. `DroppedFull` is only returned when that isn't enough.
+    ///Defaults to dropping every 2nd push above 3/4 of the capacity, change that with
+    ///`set_adaptive_drop`. Only the producer should call this
+    pub fn push_adaptive(&self, value: T) -> PushOutcome<T> {
+        let occupancy = self
+            .head
+            .load(RELAXED)
+            .wrapping_sub(self.tail.load(ACQUIRE));
+
+        unsafe {
+            let above = self.above_watermark.get();
+            if occupancy <= self.high_watermark.load(RELAXED) {
+                above.write(0);
+            } else {
+                above.write(above.read() + 1);
+                if above.read() >= self.drop_every.load(RELAXED) {
+                    above.write(0);
+                    trace_op!(
+                        kind = Self::KIND,
+                        occupancy,
+                        "push dropped above the watermark"
+                    );
+                    return PushOutcome::DroppedProactive(value);
+                }
+            }
+        }
+
+        match self.push_seq(value) {
+            Ok(_) => PushOutcome::Accepted,
+            Err(value) => PushOutcome::DroppedFull(value),
+        }
+    }
+
+    ///Configures `push_adaptive` to drop every `drop_every`th push while more than
+    ///`high_watermark` items are buffered. `drop_every == 1` drops everything above the
+    ///watermark. Panics if `drop_every` is 0 or `high_watermark` isn't below `N`
+    pub fn set_adaptive_drop(&self, high_watermark: usize, drop_every: usize) {
+        assert!(drop_every > 0, "drop_every has to be at least 1");
+        assert!(
+            high_watermark < N,
+            "High watermark {high_watermark} has to be below the capacity {N}"
+        );
+        self.high_watermark.store(high_watermark, RELAXED);
+        self.drop_every.store(drop_every, RELAXED);
+    }
+
+    ///Skips the push and returns `Ok(false)` if the last pushed item is still unread and
+    ///equal to `value`, otherwise pushes it like `push` and returns `Ok(true)`.
+    ///Handy for change notifications where repeats carry no news. `T: Copy` because the
+    ///consumer may be popping that very item while it is compared.
+    ///Only the producer should call this
+    pub fn push_coalesce(&self, value: T) -> Result<bool, T>
+    where
+        T: PartialEq + Copy,
+    {
+        let head = self.head.load(RELAXED);
+        if self.tail.load(ACQUIRE) != head {
+            let last = unsafe {
+                let buffer_ptr = self.buffer.get() as *const MaybeUninit<T>;
+                core::ptr::read_volatile(buffer_ptr.add(head.wrapping_sub(1) & Self::MASK))
+                    .assume_init()
+            };
+            //the item only counts as unread if the consumer has not finished popping it
+            if last == value && self.tail.load(ACQUIRE) != head {
+                return Ok(false);
+            }
+        }
+
+        self.push_seq(value).map(|_| true)
+    }
+
+    ///Pushes clones of as many `values` as fit, publishing them every `chunk` items instead
+    ///of once at the end, so the consumer can start on a large batch before all of it is
+    ///written. Smaller chunks mean lower latency but more release stores.
+    ///Returns how many were pushed. Only the producer should call this
+    pub fn push_slice_streaming(&self, values: &[T], chunk: usize) -> usize
+    where
+        T: Clone,
+    {
+        assert!(chunk != 0, "Chunk size must be non-zero");
+
+        let mut head = self.head.load(RELAXED);
+        let mut tail = unsafe { self.cached_tail.get().read() };
+        if N - head.wrapping_sub(tail) < values.len() {
+            tail = self.refresh_cached_tail();
+        }
+        let count = values.len().min(N - head.wrapping_sub(tail));
+
+        let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
+        for run in values[..count].chunks(chunk) {
+            for value in run {
+                unsafe {
+                    (*buffer_ptr.add(head & Self::MASK)).write(value.clone());
+                }
+                #[cfg(feature = "record")]
+                self.op_log.record(Op::Push, head);
+                #[cfg(feature = "timing")]
+                self.stamp(head, 1);
+                head = head.wrapping_add(1);
+            }
+            self.head.store(head, RELEASE);
+        }
+
+        if count != 0 {
+            self.wake_consumer();
+            with_metrics!(self.record_lag(head.wrapping_sub(self.tail.load(RELAXED))));
+            trace_op!(
+                kind = Self::KIND,
+                occupancy = head.wrapping_sub(self.tail.load(RELAXED)),
+                pushed = count,
+                "push_slice_streaming"
+            );
+        }
+        count
+    }
+
+    ///Copies as many of `data` as fit with at most two memcpys, one on each side of the
+    ///wrap point, and publishes them with a single head store. Returns how many were
+    ///pushed. Only the producer should call this
+    pub fn push_slice(&self, data: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let head = self.head.load(RELAXED);
+        let mut tail = unsafe { self.cached_tail.get().read() };
+        if N - head.wrapping_sub(tail) < data.len() {
+            tail = self.refresh_cached_tail();
+        }
+        let count = data.len().min(N - head.wrapping_sub(tail));
+        if count == 0 {
+            return 0;
+        }
+
+        let start = head & Self::MASK;
+        let first = count.min(N - start);
+        unsafe {
+            //writing through raw pointers, the consumer may be reading other slots
+            let buffer_ptr = self.buffer.get() as *mut T;
+            core::ptr::copy_nonoverlapping(data.as_ptr(), buffer_ptr.add(start), first);
+            core::ptr::copy_nonoverlapping(data.as_ptr().add(first), buffer_ptr, count - first);
+        }
+
+        #[cfg(feature = "record")]
+        self.record_run(Op::Push, head, count);
+        #[cfg(feature = "timing")]
+        self.stamp(head, count);
+        let head = head.wrapping_add(count);
+        self.head.store(head, RELEASE);
+        self.wake_consumer();
+        with_metrics!(self.record_lag(head.wrapping_sub(self.tail.load(RELAXED))));
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = head.wrapping_sub(self.tail.load(RELAXED)),
+            pushed = count,
+            "push_slice"
+        );
+        count
+    }
+
+    ///Clones as many of `values` as fit, in order, and publishes them with a single head
+    ///store. Returns how many were pushed. If a clone panics the items cloned before it
+    ///are still published, so none of them leak. Only the producer should call this
+    pub fn push_slice_clone(&self, values: &[T]) -> usize
+    where
+        T: Clone,
+    {
+        ///Publishes whatever was cloned so far when dropped, panic or not
+        struct Publish<'a, T, const N: usize> {
+            buffer: &'a AtomicRingBufferSpsc<T, N>,
+            start: usize,
+            head: usize,
+        }
+        impl<T, const N: usize> Drop for Publish<'_, T, N> {
+            fn drop(&mut self) {
+                if self.head == self.start {
+                    return;
+                }
+                let buffer = self.buffer;
+                buffer.head.store(self.head, RELEASE);
+                buffer.wake_consumer();
+                with_metrics!(buffer.record_lag(self.head.wrapping_sub(buffer.tail.load(RELAXED))));
+                trace_op!(
+                    kind = AtomicRingBufferSpsc::<T, N>::KIND,
+                    occupancy = self.head.wrapping_sub(buffer.tail.load(RELAXED)),
+                    pushed = self.head.wrapping_sub(self.start),
+                    "push_slice_clone"
+                );
+            }
+        }
+
+        let head = self.head.load(RELAXED);
+        let mut tail = unsafe { self.cached_tail.get().read() };
+        if N - head.wrapping_sub(tail) < values.len() {
+            tail = self.refresh_cached_tail();
+        }
+        let count = values.len().min(N - head.wrapping_sub(tail));
+
+        let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
+        let mut publish = Publish {
+            buffer: self,
+            start: head,
+            head,
+        };
+        for value in &values[..count] {
+            let value = value.clone();
+            unsafe {
+                (*buffer_ptr.add(publish.head & Self::MASK)).write(value);
+            }
+            #[cfg(feature = "record")]
+            self.op_log.record(Op::Push, publish.head);
+            #[cfg(feature = "timing")]
+            self.stamp(publish.head, 1);
+            publish.head = publish.head.wrapping_add(1);
+        }
+        drop(publish);
+
+        count
+    }
+
+    ///Pops, yielding to the async runtime between attempts while the buffer is empty
+    ///instead of spinning on the executor thread. Gives up with None after a bounded
+    ///number of yields, meant for buffers that are only briefly empty.
+    ///Only the consumer should call this
+    #[cfg(feature = "async")]
+    pub async fn pop_cooperative(&self) -> Option<T> {
+        for _ in 0..crate::wakers::COOPERATIVE_RETRIES {
+            if let Some(value) = self.pop() {
+                return Some(value);
+            }
+            crate::wakers::yield_now().await;
+        }
+        self.pop()
+    }
+
+    ///Waits for the next item without spinning, the task is parked until the producer
+    ///pushes. There is a single waker slot, so only the consumer may await this
+    #[cfg(feature = "async")]
+    pub async fn recv(&self) -> T {
+        core::future::poll_fn(|cx| {
+            if let Some(value) = self.pop() {
+                return core::task::Poll::Ready(value);
+            }
+
+            self.pop_waker.register(cx.waker());
+            //a push may have landed before we registered
+            match self.pop() {
+                Some(value) => core::task::Poll::Ready(value),
+                None => core::task::Poll::Pending,
+            }
+        })
+        .await
     }
 
     pub fn pop(&self) -> Option<T> {
-        let tail = self.tail.load(Ordering::Relaxed);
+        self.pop_seq().map(|(_, value)| value)
+    }
+
+    ///Like `pop` but also returns the ticket the item was pushed with
+    pub fn pop_ticketed(&self) -> Option<(Ticket, T)> {
+        self.pop_seq().map(|(seq, value)| (Ticket { seq }, value))
+    }
+
+    #[inline(always)]
+    fn pop_seq(&self) -> Option<(usize, T)> {
+        let tail = self.tail.load(RELAXED);
 
         let mut head;
         unsafe {
@@ -73,13 +516,11 @@ impl<T, const N: usize> AtomicRingBufferSpsc<T, N> {
         }
 
         if tail == head {
-            head = self.head.load(Ordering::Acquire);
-
-            unsafe {
-                self.cached_head.get().write(head);
-            }
+            head = self.refresh_cached_head();
 
             if head == tail {
+                with_metrics!(self.metrics.record_poll(false));
+                with_metrics!(self.metrics.record_occupancy(0, N));
                 return None;
             }
         }
@@ -87,160 +528,2062 @@ impl<T, const N: usize> AtomicRingBufferSpsc<T, N> {
         let value;
         unsafe {
             let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
-            let slot_ptr = buffer_ptr.add(tail & (N - 1));
+            let slot_ptr = buffer_ptr.add(tail & Self::MASK);
             value = (*slot_ptr).assume_init_read();
         }
 
-        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        #[cfg(feature = "record")]
+        self.op_log.record(Op::Pop, tail);
+        self.tail.store(tail.wrapping_add(1), RELEASE);
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = self.head.load(RELAXED).wrapping_sub(tail.wrapping_add(1)),
+            "pop"
+        );
+        with_metrics!(self.metrics.record_poll(true));
+        with_metrics!(self.metrics.record_occupancy(self.len(), N));
 
-        Some(value)
+        Some((tail, value))
     }
-    pub fn read_head(&self) -> usize {
-        self.head.load(Ordering::Acquire) % N
+
+    ///Share of pops that found the buffer empty. A ratio close to 1.0 means the consumer
+    ///mostly spins on an empty buffer and would be better off parking
+    #[cfg(feature = "metrics")]
+    pub fn empty_poll_ratio(&self) -> f64 {
+        self.metrics.empty_poll_ratio()
     }
 
-    pub fn read_tail(&self) -> usize {
-        self.tail.load(Ordering::Acquire) % N
+    ///True when the buffer sat above 90% full on average over roughly the last 64 pushes
+    ///and pops, a sign it is too small for the load and will start rejecting pushes.
+    ///Clears again once the consumer catches up
+    #[cfg(feature = "metrics")]
+    pub fn is_chronically_full(&self) -> bool {
+        self.metrics.is_chronically_full()
     }
 
-    pub fn exists(&self, index: usize) -> bool {
-        let mut tail = self.tail.load(Ordering::Acquire);
-        let mut head = self.head.load(Ordering::Acquire);
-        if head == tail {
-            return false;
+    ///All metrics counters read in one go with relaxed loads, so they may be slightly
+    ///out of step with each other while the buffer is in use
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> crate::Metrics {
+        self.metrics.snapshot()
+    }
+
+    ///Zeroes every metrics counter, returning the values they had right before.
+    ///Each counter is swapped on its own, an operation racing with the reset is counted
+    ///either in the returned values or after the reset, never in both
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&self) -> crate::Metrics {
+        self.metrics.reset()
+    }
+
+    ///Optional hint for the producer to call while idle. Reloads the producer's cached copy
+    ///of tail so the next push does not have to, it does not touch the consumer's cache.
+    ///Only the producer should call this
+    pub fn refresh_producer_cache(&self) {
+        self.refresh_cached_tail();
+    }
+
+    ///Optional hint for the consumer to call while idle. Reloads the consumer's cached copy
+    ///of head so the next pop does not have to, it does not touch the producer's cache.
+    ///Only the consumer should call this
+    pub fn refresh_consumer_cache(&self) {
+        self.refresh_cached_head();
+    }
+
+    #[inline]
+    fn refresh_cached_tail(&self) -> usize {
+        #[cfg(test)]
+        self.cache_refreshes.fetch_add(1, RELAXED);
+        let tail = self.tail.load(ACQUIRE);
+        unsafe {
+            self.cached_tail.get().write(tail);
         }
-        head &= N - 1;
-        tail &= N - 1;
-        if head > tail {
-            head > index && index > tail
-        } else {
-            !(index >= head && tail > index)
+        tail
+    }
+
+    #[inline]
+    fn refresh_cached_head(&self) -> usize {
+        #[cfg(test)]
+        self.cache_refreshes.fetch_add(1, RELAXED);
+        let head = self.head.load(ACQUIRE);
+        unsafe {
+            self.cached_head.get().write(head);
         }
+        head
     }
-}
 
-impl<T, const N: usize> Drop for AtomicRingBufferSpsc<T, N> {
-    fn drop(&mut self) {
-        if std::mem::needs_drop::<T>() {
-            let head = self.head.load(Ordering::Relaxed);
-            let tail = self.tail.load(Ordering::Relaxed);
+    ///Commits the consumption of `n` items that the consumer already took out of the buffer.
+    ///The items are not dropped! Fails without doing anything if fewer than `n` are buffered.
+    ///Only the consumer should call this
+    #[allow(clippy::result_unit_err)]
+    pub fn advance_tail(&self, n: usize) -> Result<(), ()> {
+        let tail = self.tail.load(RELAXED);
+        let head = self.refresh_cached_head();
 
-            let mut current = tail;
-            while current != head {
-                let mask = current & (N - 1);
-                unsafe {
-                    let slot = (*self.buffer.get()).get_unchecked_mut(mask);
-                    std::ptr::drop_in_place(slot.as_mut_ptr());
-                }
-                current = current.wrapping_add(1);
+        if n > head.wrapping_sub(tail) {
+            return Err(());
+        }
+
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, n);
+        self.tail.store(tail.wrapping_add(n), RELEASE);
+        Ok(())
+    }
+
+    ///Calls `f` on the item the next pop would return, without popping it. Only the
+    ///consumer should call this, and `f` must not pop from the buffer, that would free
+    ///the item `f` is still reading. [`Consumer::peek_with`] borrows the consumer
+    ///mutably so that can't happen, and [`Consumer::read_cow`] holds on to the borrow
+    ///instead
+    pub fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.front().map(|value| f(unsafe { &*value }))
+    }
+
+    ///The oldest item, valid until the consumer pops it
+    #[inline]
+    fn front(&self) -> Option<*const T> {
+        let tail = self.tail.load(RELAXED);
+        let mut head = unsafe { self.cached_head.get().read() };
+        if head == tail {
+            head = self.refresh_cached_head();
+            if head == tail {
+                return None;
             }
         }
+
+        unsafe {
+            let buffer_ptr = self.buffer.get() as *const MaybeUninit<T>;
+            Some((*buffer_ptr.add(tail & Self::MASK)).as_ptr())
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::thread;
+    ///Pops the next item along with how long it sat in the buffer since its push.
+    ///Only the consumer should call this
+    #[cfg(feature = "timing")]
+    pub fn pop_timed(&self) -> Option<(T, Duration)> {
+        //the stamp has to be read while the slot is still ours, before pop hands it back
+        self.front()?;
+        let tail = self.tail.load(RELAXED);
+        let stamp = unsafe { (*self.stamps.get())[tail & Self::MASK] };
+        let (_, value) = self.pop_seq()?;
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        Some((value, Duration::from_nanos(now.saturating_sub(stamp))))
+    }
 
-    #[test]
-    fn test_simple_push_pop() {
-        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+    ///Stamps `count` slots from position `from` with the current time
+    #[cfg(feature = "timing")]
+    #[inline]
+    fn stamp(&self, from: usize, count: usize) {
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        let stamps = self.stamps.get() as *mut u64;
+        for offset in 0..count {
+            unsafe {
+                stamps
+                    .add(from.wrapping_add(offset) & Self::MASK)
+                    .write(now)
+            };
+        }
+    }
 
-        assert!(buffer.push(1).is_ok());
-        assert!(buffer.push(2).is_ok());
-        assert!(buffer.push(3).is_ok());
-        assert!(buffer.push(4).is_ok());
+    ///Logs `count` items pushed or popped from position `from` on, one record each as if
+    ///they had gone through `push` or `pop` one at a time
+    #[cfg(feature = "record")]
+    fn record_run(&self, op: Op, from: usize, count: usize) {
+        for offset in 0..count {
+            self.op_log.record(op, from.wrapping_add(offset));
+        }
+    }
 
-        assert!(buffer.push(5).is_err());
+    ///Pops exactly `W` items into an array, oldest first, without allocating.
+    ///If fewer than `W` are buffered nothing is consumed and `None` is returned.
+    ///Only the consumer should call this
+    pub fn pop_window<const W: usize>(&self) -> Option<[T; W]> {
+        let tail = self.tail.load(RELAXED);
+        let mut head = unsafe { self.cached_head.get().read() };
 
-        assert_eq!(buffer.pop(), Some(1));
-        assert_eq!(buffer.pop(), Some(2));
+        if head.wrapping_sub(tail) < W {
+            head = self.refresh_cached_head();
+            if head.wrapping_sub(tail) < W {
+                return None;
+            }
+        }
 
-        assert!(buffer.push(5).is_ok());
+        let window: [T; W] = core::array::from_fn(|offset| unsafe {
+            let buffer_ptr = self.buffer.get() as *const MaybeUninit<T>;
+            (*buffer_ptr.add(tail.wrapping_add(offset) & Self::MASK)).assume_init_read()
+        });
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, W);
+        self.tail.store(tail.wrapping_add(W), RELEASE);
+        Some(window)
+    }
 
-        assert_eq!(buffer.pop(), Some(3));
-        assert_eq!(buffer.pop(), Some(4));
-        assert_eq!(buffer.pop(), Some(5));
-        assert_eq!(buffer.pop(), None);
+    ///Moves everything currently buffered into `into`, replacing what it held. The items
+    ///are copied out in at most two runs and the tail is published once.
+    ///Only the consumer should call this
+    pub fn drain_reuse(&self, into: &mut PopBuffer<T>) {
+        let items = into.vec_mut();
+        items.clear();
+
+        let tail = self.tail.load(RELAXED);
+        let head = self.refresh_cached_head();
+        let len = head.wrapping_sub(tail);
+        if len == 0 {
+            return;
+        }
+
+        items.reserve(len);
+        let start = tail & Self::MASK;
+        let first = len.min(N - start);
+        unsafe {
+            let buffer_ptr = self.buffer.get() as *const T;
+            let out = items.as_mut_ptr();
+            core::ptr::copy_nonoverlapping(buffer_ptr.add(start), out, first);
+            core::ptr::copy_nonoverlapping(buffer_ptr, out.add(first), len - first);
+            items.set_len(len);
+        }
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, len);
+        self.tail.store(tail.wrapping_add(len), RELEASE);
     }
 
-    #[test]
-    fn test_threaded_spsc_ordering() {
-        let buffer = AtomicRingBufferSpsc::<usize, 16>::new();
-        let consumer_buffer = buffer.clone();
+    ///Copies up to `out.len()` items into `out` with at most two memcpys, one on each
+    ///side of the wrap point, and hands their slots back with a single tail store.
+    ///Never reads past the head, whatever does not fit stays buffered.
+    ///Returns how many were copied. Only the consumer should call this
+    pub fn pop_slice(&self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let tail = self.tail.load(RELAXED);
+        let mut head = unsafe { self.cached_head.get().read() };
+        if head.wrapping_sub(tail) < out.len() {
+            head = self.refresh_cached_head();
+        }
+        let count = out.len().min(head.wrapping_sub(tail));
+        if count == 0 {
+            with_metrics!(self.metrics.record_poll(false));
+            return 0;
+        }
 
-        let thread_count = 100_000;
+        let start = tail & Self::MASK;
+        let first = count.min(N - start);
+        unsafe {
+            let buffer_ptr = self.buffer.get() as *const T;
+            core::ptr::copy_nonoverlapping(buffer_ptr.add(start), out.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(buffer_ptr, out.as_mut_ptr().add(first), count - first);
+        }
 
-        let producer = thread::spawn(move || {
-            for i in 0..thread_count {
-                while buffer.push(i).is_err() {
-                    std::hint::spin_loop();
-                }
-            }
-        });
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, count);
+        self.tail.store(tail.wrapping_add(count), RELEASE);
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = self
+                .head
+                .load(RELAXED)
+                .wrapping_sub(tail.wrapping_add(count)),
+            popped = count,
+            "pop_slice"
+        );
+        with_metrics!(self.metrics.record_poll(true));
+        count
+    }
 
-        let consumer = thread::spawn(move || {
-            for i in 0..thread_count {
-                loop {
-                    if let Some(val) = consumer_buffer.pop() {
-                        assert_eq!(val, i, "Items received out of order!");
-                        break;
-                    }
-                    std::hint::spin_loop();
-                }
+    ///At least once delivery: returns a clone of the next item that was not delivered yet
+    ///but keeps the item, and its slot, until `ack` says it was processed. The tail only
+    ///moves on ack, so unacked items count against the producer's capacity.
+    ///Only the consumer should call this, use either this or `pop`, not both
+    pub fn deliver(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let tail = self.tail.load(RELAXED);
+        let mut delivered = self.delivered.load(RELAXED);
+        //a plain pop may have moved the tail past the cursor
+        if delivered.wrapping_sub(tail) > N {
+            delivered = tail;
+        }
+
+        let mut head = unsafe { self.cached_head.get().read() };
+        if delivered == head {
+            head = self.refresh_cached_head();
+            if delivered == head {
+                with_metrics!(self.metrics.record_poll(false));
+                return None;
             }
-        });
+        }
 
-        producer.join().unwrap();
-        consumer.join().unwrap();
+        let value = unsafe {
+            let buffer_ptr = self.buffer.get() as *const MaybeUninit<T>;
+            (*buffer_ptr.add(delivered & Self::MASK))
+                .assume_init_ref()
+                .clone()
+        };
+        self.delivered.store(delivered.wrapping_add(1), RELAXED);
+        with_metrics!(self.metrics.record_poll(true));
+        Some(value)
     }
 
-    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
-
-    #[derive(Debug)]
-    struct DropTracker;
+    ///Acknowledges up to `n` of the oldest delivered items, dropping them and freeing their
+    ///slots like a pop would. Returns how many were acked, never more than were delivered.
+    ///Only the consumer should call this
+    pub fn ack(&self, n: usize) -> usize {
+        let tail = self.tail.load(RELAXED);
+        let n = n.min(self.unacked());
+        if n == 0 {
+            return 0;
+        }
 
-    impl Drop for DropTracker {
-        fn drop(&mut self) {
-            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
+        for offset in 0..n {
+            let seq = tail.wrapping_add(offset);
+            unsafe { (*buffer_ptr.add(seq & Self::MASK)).assume_init_drop() };
         }
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, n);
+        self.tail.store(tail.wrapping_add(n), RELEASE);
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = self.head.load(RELAXED).wrapping_sub(tail.wrapping_add(n)),
+            acked = n,
+            "ack"
+        );
+        with_metrics!(self.metrics.record_occupancy(self.len(), N));
+        n
     }
 
-    #[test]
-    fn test_drop_cleanup() {
-        DROP_COUNTER.store(0, Ordering::Relaxed);
+    ///Starts delivering again from the oldest unacked item.
+    ///Only the consumer should call this
+    pub fn redeliver(&self) {
+        let tail = self.tail.load(RELAXED);
+        self.delivered.store(tail, RELAXED);
+    }
 
-        {
-            let buffer = AtomicRingBufferSpsc::<DropTracker, 8>::new();
+    ///Items delivered but not acked yet. Safe to call from any thread, though from
+    ///anywhere but the consumer it is only a snapshot
+    pub fn unacked(&self) -> usize {
+        let tail = self.tail.load(ACQUIRE);
+        let delivered = self.delivered.load(RELAXED);
+        match delivered.wrapping_sub(tail) {
+            unacked if unacked > N => 0,
+            unacked => unacked,
+        }
+    }
 
-            for _ in 0..5 {
-                buffer.push(DropTracker).unwrap();
-            }
+    ///Splits the buffer into its two ends. Neither handle can be cloned or shared between
+    ///threads, so owning one is what makes you the single producer or the single
+    ///consumer. Needs the only handle to the buffer, otherwise another owner could still
+    ///push or pop behind the handles' backs and it is handed back untouched.
+    ///Called as `AtomicRingBufferSpsc::split(buffer)` since our Arc can't be a receiver
+    pub fn split(mut this: Arc<Self>) -> Result<(Producer<T, N>, Consumer<T, N>), Arc<Self>> {
+        if Arc::get_mut(&mut this).is_none() {
+            return Err(this);
+        }
+        Ok((
+            Producer {
+                buffer: this.clone(),
+                _not_sync: PhantomData,
+            },
+            Consumer {
+                buffer: this,
+                _not_sync: PhantomData,
+            },
+        ))
+    }
 
-            buffer.pop();
-            buffer.pop();
+    ///Moves everything buffered into a new MPMC buffer of the same capacity, oldest first,
+    ///for when a pipeline outgrows its single consumer. Needs the only handle to the
+    ///buffer, otherwise it is handed back untouched. Called as
+    ///`AtomicRingBufferSpsc::into_mpmc(buffer)`, like `split`
+    pub fn into_mpmc(mut this: Arc<Self>) -> Result<Arc<AtomicRingBufferMpmc<T, N>>, Arc<Self>> {
+        let Some(spsc) = Arc::get_mut(&mut this) else {
+            return Err(this);
+        };
+        let mpmc = AtomicRingBufferMpmc::new();
+        while let Some(value) = spsc.pop() {
+            if mpmc.push(value).is_err() {
+                unreachable!("a fresh buffer of the same capacity has room for every item");
+            }
+        }
+        Ok(mpmc)
+    }
 
-            assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 2);
+    ///Starts a read that only consumes what it took once committed. Dropping the
+    ///transaction without `commit` leaves every item it took in the buffer.
+    ///[`Consumer::begin_read`] is the safe way to get one.
+    ///
+    ///# Safety
+    ///
+    ///Only the consumer may call this, and it must not pop or use any other consumer
+    ///method while the transaction is open, or `commit` drops items a second time
+    pub unsafe fn begin_read(&self) -> ReadTransaction<'_, T, N> {
+        let tail = self.tail.load(RELAXED);
+        ReadTransaction {
+            buffer: self,
+            tail,
+            cursor: Cell::new(tail),
         }
+    }
 
-        assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 5);
+    ///Pops items in groups of up to `size`, stopping once the buffer is empty
+    pub fn chunks(&self, size: usize) -> Chunks<'_, T, N> {
+        assert!(size != 0, "Chunk size must be non-zero");
+        Chunks { buffer: self, size }
     }
 
-    #[test]
-    fn test_zst() {
-        struct Zst;
+    ///How far the consumer is behind the producer, the same as `len`
+    pub fn lag(&self) -> usize {
+        self.len()
+    }
 
-        let buffer = AtomicRingBufferSpsc::<Zst, 4>::new();
+    ///Highest lag seen by a push since creation, the last `reset_lag_stats` or the last
+    ///`reset_metrics`
+    #[cfg(feature = "metrics")]
+    pub fn max_lag_since_reset(&self) -> usize {
+        self.metrics.max_lag()
+    }
 
-        assert!(buffer.push(Zst).is_ok());
-        assert!(buffer.push(Zst).is_ok());
-        assert!(buffer.push(Zst).is_ok());
-        assert!(buffer.push(Zst).is_ok());
-        assert!(buffer.push(Zst).is_err());
+    #[cfg(feature = "metrics")]
+    pub fn reset_lag_stats(&self) {
+        self.metrics.reset_lag();
+    }
 
-        assert!(buffer.pop().is_some());
+    #[inline(always)]
+    fn wake_consumer(&self) {
+        #[cfg(feature = "async")]
+        self.pop_waker.wake();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[inline]
+    fn record_lag(&self, lag: usize) {
+        self.metrics.record_lag(lag, N);
+    }
+
+    ///Best effort copy of the buffered items, oldest first, appended to `out`. Meant for
+    ///cheap dashboards: it does not synchronize with the producer or consumer, so while
+    ///they are running the copy may be stale or miss items. Do not use it for anything
+    ///correctness sensitive. Each item is checked after it is copied, if the consumer
+    ///released its slot meanwhile the producer may be overwriting it and the copy is
+    ///thrown away, so a torn value is never returned. On a quiescent buffer it is exact
+    pub fn snapshot_lossy(&self, out: &mut Vec<T>)
+    where
+        T: Copy,
+    {
+        let tail = self.tail.load(RELAXED);
+        //acquire so every slot below head has been written at least once
+        let head = self.head.load(ACQUIRE);
+        let len = head.wrapping_sub(tail).min(N);
+
+        out.reserve(len);
+        let buffer_ptr = self.buffer.get() as *const MaybeUninit<T>;
+        for offset in 0..len {
+            let pos = tail.wrapping_add(offset);
+            let value = unsafe { core::ptr::read_volatile(buffer_ptr.add(pos & Self::MASK)) };
+            //the producer only reuses the slot once tail has passed it
+            fence(ACQUIRE);
+            if pos.wrapping_sub(self.tail.load(RELAXED)) < N {
+                out.push(unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    ///Takes the recorded pushes and pops out of the operation log, oldest first.
+    ///Each operation is logged right before it is published, so a pop never shows up
+    ///ahead of the push it took. The log keeps the most recent 1024 operations
+    #[cfg(feature = "record")]
+    pub fn dump_log(&self) -> Vec<OpRecord> {
+        self.op_log.dump()
+    }
+
+    ///Pushes default items until `level` are buffered, for benchmark and test setup.
+    ///Not meant to race with other producers
+    #[doc(hidden)]
+    pub fn fill_to(&self, level: usize)
+    where
+        T: Default,
+    {
+        crate::fill_with_default(N, level, self.len(), |value| self.push(value));
+    }
+
+    ///How many full laps the consumer has made around the storage, total throughput in
+    ///units of whole buffers. Wraps along with the raw tail counter after `usize::MAX` pops
+    pub fn wrap_count(&self) -> usize {
+        self.tail.load(ACQUIRE) / N
+    }
+
+    ///Drops everything buffered. Needs the buffer uniquely owned, e.g. through
+    ///`Arc::get_mut`, so no push or pop can be running
+    pub fn clear(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        let buffer = self.buffer.get_mut();
+
+        let mut current = tail;
+        while current != head {
+            unsafe {
+                buffer
+                    .get_unchecked_mut(current & Self::MASK)
+                    .assume_init_drop()
+            };
+            current = current.wrapping_add(1);
+        }
+        #[cfg(feature = "record")]
+        self.record_run(Op::Pop, tail, head.wrapping_sub(tail));
+        *self.tail.get_mut() = head;
+        *self.cached_head.get_mut() = head;
+        *self.cached_tail.get_mut() = head;
+        *self.delivered.get_mut() = head;
+    }
+
+    ///Whether a push would find a free slot right now, so the producer can skip building
+    ///an expensive `T`. Exact for the producer since only pops race with it and those
+    ///only free slots. Only the producer should call this
+    pub fn would_accept(&self) -> bool {
+        let head = self.head.load(RELAXED);
+        let tail = unsafe { self.cached_tail.get().read() };
+        head.wrapping_sub(tail) != N || head.wrapping_sub(self.refresh_cached_tail()) != N
+    }
+
+    ///The sequence number the next pushed item will get, the same as its `Ticket::seq`.
+    ///Wraps after `usize::MAX` pushes
+    pub fn next_push_seq(&self) -> usize {
+        self.head.load(ACQUIRE)
+    }
+
+    ///The sequence number of the item the next pop will return
+    pub fn next_pop_seq(&self) -> usize {
+        self.tail.load(ACQUIRE)
+    }
+
+    ///Number of buffered items. Tail is re-read until it is stable around the head load,
+    ///so the snapshot is consistent and never exceeds N
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let head = self.head.load(ACQUIRE);
+            if self.tail.load(ACQUIRE) == tail {
+                return head.wrapping_sub(tail);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    ///How full the buffer is, from 0.0 (empty) to 1.0 (full)
+    pub fn fill_ratio(&self) -> f64 {
+        self.len() as f64 / N as f64
+    }
+
+    ///Panics if the buffer's core invariant is broken: tail never passes head and head
+    ///is never more than N ahead of tail. Meant to be called from tests or fuzzers
+    ///while no push or pop is running
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn check_invariants(&self) {
+        let head = self.head.load(ACQUIRE);
+        let tail = self.tail.load(ACQUIRE);
+        let len = head.wrapping_sub(tail);
+        assert!(
+            len <= N,
+            "head {head} and tail {tail} are {len} apart, more than N = {N}"
+        );
+    }
+
+    ///Head, tail and the values derived from them, from a single consistent read
+    pub fn geometry(&self) -> Geometry {
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let head = self.head.load(ACQUIRE);
+            if self.tail.load(ACQUIRE) == tail {
+                return Geometry::new(Self::MASK, head, tail);
+            }
+        }
+    }
+
+    pub fn read_head(&self) -> usize {
+        self.head.load(ACQUIRE) & Self::MASK
+    }
+
+    pub fn read_tail(&self) -> usize {
+        self.tail.load(ACQUIRE) & Self::MASK
+    }
+
+    ///Whether slot `index` holds an item. The slot at the tail is the next one to pop
+    ///so it counts, the slot at the head is the next one to write so it doesn't
+    pub fn exists(&self, index: usize) -> bool {
+        let mut tail = self.tail.load(ACQUIRE);
+        let mut head = self.head.load(ACQUIRE);
+        if head == tail {
+            return false;
+        }
+        head &= Self::MASK;
+        tail &= Self::MASK;
+        if head > tail {
+            head > index && index >= tail
+        } else {
+            !(index >= head && tail > index)
+        }
+    }
+}
+
+///Guard returned by [`Consumer::begin_read`]. It holds no lock, the slots
+///it took stay reserved simply because the tail has not moved past them yet
+pub struct ReadTransaction<'a, T, const N: usize> {
+    buffer: &'a AtomicRingBufferSpsc<T, N>,
+    tail: usize,
+    cursor: Cell<usize>,
+}
+
+impl<T, const N: usize> ReadTransaction<'_, T, N> {
+    ///Borrows the next item, the borrow lasts as long as the transaction
+    pub fn take(&self) -> Option<&T> {
+        let cursor = self.cursor.get();
+        let mut head = unsafe { self.buffer.cached_head.get().read() };
+        if cursor == head {
+            head = self.buffer.refresh_cached_head();
+            if cursor == head {
+                return None;
+            }
+        }
+
+        self.cursor.set(cursor.wrapping_add(1));
+        unsafe {
+            let buffer_ptr = self.buffer.buffer.get() as *const MaybeUninit<T>;
+            Some((*buffer_ptr.add(cursor & AtomicRingBufferSpsc::<T, N>::MASK)).assume_init_ref())
+        }
+    }
+
+    ///How many items were taken so far
+    pub fn taken(&self) -> usize {
+        self.cursor.get().wrapping_sub(self.tail)
+    }
+
+    ///Consumes everything taken: the items are dropped and their slots handed back to
+    ///the producer
+    pub fn commit(self) {
+        let cursor = self.cursor.get();
+        let mut current = self.tail;
+        while current != cursor {
+            unsafe {
+                let buffer_ptr = self.buffer.buffer.get() as *mut MaybeUninit<T>;
+                (*buffer_ptr.add(current & AtomicRingBufferSpsc::<T, N>::MASK)).assume_init_drop();
+            }
+            current = current.wrapping_add(1);
+        }
+        #[cfg(feature = "record")]
+        self.buffer
+            .record_run(Op::Pop, self.tail, cursor.wrapping_sub(self.tail));
+        self.buffer.tail.store(cursor, RELEASE);
+    }
+}
+
+///Iterator returned by [`AtomicRingBufferSpsc::chunks`]
+pub struct Chunks<'a, T, const N: usize> {
+    buffer: &'a AtomicRingBufferSpsc<T, N>,
+    size: usize,
+}
+
+impl<T, const N: usize> Iterator for Chunks<'_, T, N> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.size.min(N));
+        while chunk.len() < self.size {
+            match self.buffer.pop() {
+                Some(value) => chunk.push(value),
+                None => break,
+            }
+        }
+        if chunk.is_empty() { None } else { Some(chunk) }
+    }
+}
+
+impl<T, const N: usize> Drop for AtomicRingBufferSpsc<T, N> {
+    fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        let observer = self.drop_observer.take();
+        #[cfg(not(feature = "std"))]
+        let observer: Option<DropObserver<T>> = None;
+        if core::mem::needs_drop::<T>() || observer.is_some() {
+            let head = self.head.load(RELAXED);
+            let tail = self.tail.load(RELAXED);
+
+            let mut current = tail;
+            while current != head {
+                let mask = current & Self::MASK;
+                unsafe {
+                    let slot = (*self.buffer.get()).get_unchecked_mut(mask);
+                    if let Some(observer) = &observer {
+                        (observer.0)(slot.assume_init_ref());
+                    }
+                    core::ptr::drop_in_place(slot.as_mut_ptr());
+                }
+                current = current.wrapping_add(1);
+            }
+        }
+    }
+}
+
+///Producing end returned by [`AtomicRingBufferSpsc::split`]. It can be sent to another
+///thread but not shared, two threads pushing through one `&Producer` would race
+///
+///```compile_fail
+///use lockless_datastructures::AtomicRingBufferSpsc;
+///
+///fn shared<T: Sync>(_: &T) {}
+///
+///let buffer = AtomicRingBufferSpsc::<u32, 4>::new();
+///let Ok((producer, _consumer)) = AtomicRingBufferSpsc::split(buffer) else {
+///    unreachable!()
+///};
+///shared(&producer);
+///```
+pub struct Producer<T, const N: usize> {
+    buffer: Arc<AtomicRingBufferSpsc<T, N>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T, const N: usize> Producer<T, N> {
+    pub fn push(&self, value: T) -> Result<(), TryPushError<T>> {
+        self.buffer.push(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+///Consuming end returned by [`AtomicRingBufferSpsc::split`]. Like the [`Producer`] it
+///can be sent to another thread but not shared
+pub struct Consumer<T, const N: usize> {
+    buffer: Arc<AtomicRingBufferSpsc<T, N>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T, const N: usize> Consumer<T, N> {
+    pub fn pop(&self) -> Option<T> {
+        self.buffer.pop()
+    }
+
+    ///See [`AtomicRingBufferSpsc::recv`]
+    #[cfg(feature = "async")]
+    pub async fn recv(&self) -> T {
+        self.buffer.recv().await
+    }
+
+    ///Starts a read that only consumes what it took once committed, dropping the
+    ///transaction without `commit` leaves every item it took in the buffer. It borrows
+    ///the consumer mutably, so nothing else can pop until it is gone
+    ///
+    ///```compile_fail
+    ///use lockless_datastructures::AtomicRingBufferSpsc;
+    ///
+    ///let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+    ///let Ok((producer, mut consumer)) = AtomicRingBufferSpsc::split(buffer) else {
+    ///    unreachable!()
+    ///};
+    ///producer.push("a".to_string()).unwrap();
+    ///let read = consumer.begin_read();
+    ///read.take();
+    ///consumer.pop();
+    ///read.commit();
+    ///```
+    pub fn begin_read(&mut self) -> ReadTransaction<'_, T, N> {
+        unsafe { self.buffer.begin_read() }
+    }
+
+    ///Clones the next item without popping it
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.buffer.peek_with(T::clone)
+    }
+
+    ///Calls `f` on the next item without popping it. It borrows the consumer mutably,
+    ///so `f` can't pop the item out from under its own borrow
+    ///
+    ///```compile_fail
+    ///use lockless_datastructures::AtomicRingBufferSpsc;
+    ///
+    ///let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+    ///let Ok((producer, mut consumer)) = AtomicRingBufferSpsc::split(buffer) else {
+    ///    unreachable!()
+    ///};
+    ///producer.push("a".to_string()).unwrap();
+    ///consumer.peek_with(|value| {
+    ///    consumer.pop();
+    ///    value.len()
+    ///});
+    ///```
+    pub fn peek_with<R>(&mut self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.buffer.peek_with(f)
+    }
+
+    ///Borrows the oldest item without popping or cloning it, call `into_owned` on the
+    ///result only when a copy is actually needed. The borrow holds on to the consumer,
+    ///so the item can't be popped while it lives
+    pub fn read_cow(&mut self) -> Option<Cow<'_, T>>
+    where
+        T: Clone,
+    {
+        self.buffer
+            .front()
+            .map(|value| Cow::Borrowed(unsafe { &*value }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_kind() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        assert_eq!(buffer.kind(), "atomic-spsc");
+        assert_eq!(AtomicRingBufferSpsc::<i32, 4>::KIND, "atomic-spsc");
+    }
+
+    #[test]
+    fn test_simple_push_pop() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+
+        assert!(buffer.push(1).is_ok());
+        assert!(buffer.push(2).is_ok());
+        assert!(buffer.push(3).is_ok());
+        assert!(buffer.push(4).is_ok());
+
+        assert!(buffer.push(5).is_err());
+
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+
+        assert!(buffer.push(5).is_ok());
+
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), Some(4));
+        assert_eq!(buffer.pop(), Some(5));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_threaded_spsc_ordering() {
+        let buffer = AtomicRingBufferSpsc::<usize, 16>::new();
+        let consumer_buffer = buffer.clone();
+
+        let thread_count = 100_000;
+
+        let producer = thread::spawn(move || {
+            for i in 0..thread_count {
+                while buffer.push(i).is_err() {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+
+        let consumer = thread::spawn(move || {
+            for i in 0..thread_count {
+                loop {
+                    if let Some(val) = consumer_buffer.pop() {
+                        assert_eq!(val, i, "Items received out of order!");
+                        break;
+                    }
+                    std::hint::spin_loop();
+                }
+            }
+        });
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+
+    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct DropTracker;
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_drop_cleanup() {
+        DROP_COUNTER.store(0, Ordering::Relaxed);
+
+        {
+            let buffer = AtomicRingBufferSpsc::<DropTracker, 8>::new();
+
+            for _ in 0..5 {
+                buffer.push(DropTracker).unwrap();
+            }
+
+            buffer.pop();
+            buffer.pop();
+
+            assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 2);
+        }
+
+        assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let buffer = AtomicRingBufferSpsc::<i32, 16>::new();
+        for i in 0..10 {
+            buffer.push(i).unwrap();
+        }
+
+        let chunks: Vec<Vec<i32>> = buffer.chunks(3).collect();
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+
+        assert_eq!(sizes, vec![3, 3, 3, 1]);
+        assert_eq!(chunks.concat(), (0..10).collect::<Vec<_>>());
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_advance_tail() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert!(buffer.push(4).is_err());
+
+        assert_eq!(buffer.advance_tail(5), Err(()));
+        assert_eq!(buffer.pop(), Some(0));
+
+        assert_eq!(buffer.advance_tail(2), Ok(()));
+        assert!(buffer.push(4).is_ok());
+        assert!(buffer.push(5).is_ok());
+        assert!(buffer.push(6).is_ok());
+        assert!(buffer.push(7).is_err());
+
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.advance_tail(3), Ok(()));
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.advance_tail(1), Err(()));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_push_emits_trace_event() {
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<Vec<(String, String)>>>);
+
+        struct Fields<'a>(&'a mut Vec<(String, String)>);
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .push((field.name().to_string(), format!("{value:?}")));
+            }
+        }
+
+        impl Subscriber for &'static Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Vec::new();
+                event.record(&mut Fields(&mut fields));
+                self.0.lock().unwrap().push(fields);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder: &'static Recorder = Box::leak(Box::default());
+        tracing::subscriber::with_default(recorder, || {
+            let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+            buffer.push(1).unwrap();
+            buffer.push(2).unwrap();
+        });
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        let field = |name: &str| {
+            events[1]
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        assert_eq!(field("message").as_deref(), Some("push"));
+        assert_eq!(field("kind").as_deref(), Some("\"atomic-spsc\""));
+        assert_eq!(field("occupancy").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_zst() {
+        struct Zst;
+
+        let buffer = AtomicRingBufferSpsc::<Zst, 4>::new();
+
+        assert!(buffer.push(Zst).is_ok());
+        assert!(buffer.push(Zst).is_ok());
+        assert!(buffer.push(Zst).is_ok());
+        assert!(buffer.push(Zst).is_ok());
+        assert!(buffer.push(Zst).is_err());
+
+        assert!(buffer.pop().is_some());
         assert!(buffer.push(Zst).is_ok());
     }
+
+    #[test]
+    fn test_fill_ratio() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(buffer.fill_ratio(), 0.0);
+        assert!(buffer.is_empty());
+
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.fill_ratio(), 0.5);
+
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.fill_ratio(), 1.0);
+
+        buffer.pop();
+        assert_eq!(buffer.fill_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_refresh_cache() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        for i in 0..4 {
+            assert_eq!(buffer.pop(), Some(i));
+        }
+
+        buffer.refresh_producer_cache();
+        let refreshes = buffer.cache_refreshes.load(Ordering::Relaxed);
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.cache_refreshes.load(Ordering::Relaxed), refreshes);
+
+        buffer.refresh_consumer_cache();
+        let refreshes = buffer.cache_refreshes.load(Ordering::Relaxed);
+        assert_eq!(buffer.pop(), Some(4));
+        assert_eq!(buffer.cache_refreshes.load(Ordering::Relaxed), refreshes);
+    }
+
+    #[test]
+    fn test_stale_cache_reloads() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        for i in 0..4 {
+            assert_eq!(buffer.pop(), Some(i));
+        }
+
+        let refreshes = buffer.cache_refreshes.load(Ordering::Relaxed);
+        buffer.push(4).unwrap();
+        assert_eq!(
+            buffer.cache_refreshes.load(Ordering::Relaxed),
+            refreshes + 1
+        );
+    }
+
+    #[test]
+    fn test_lag() {
+        let buffer = AtomicRingBufferSpsc::<i32, 8>::new();
+        assert_eq!(buffer.lag(), 0);
+
+        for i in 0..5 {
+            buffer.push(i).unwrap();
+            assert_eq!(buffer.lag(), i as usize + 1);
+        }
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(buffer.lag(), 3);
+        while buffer.pop().is_some() {}
+        assert_eq!(buffer.lag(), 0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_max_lag() {
+        let buffer = AtomicRingBufferSpsc::<i32, 8>::new();
+        for i in 0..5 {
+            buffer.push(i).unwrap();
+        }
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(buffer.max_lag_since_reset(), 5);
+
+        buffer.reset_lag_stats();
+        assert_eq!(buffer.max_lag_since_reset(), 0);
+        buffer.push(5).unwrap();
+        assert_eq!(buffer.max_lag_since_reset(), 4);
+
+        while buffer.pop().is_some() {}
+        assert_eq!(buffer.lag(), 0);
+        assert_eq!(buffer.max_lag_since_reset(), 4);
+
+        //reset_metrics clears it along with every other counter
+        assert_eq!(buffer.reset_metrics().max_lag, 4);
+        assert_eq!(buffer.max_lag_since_reset(), 0);
+    }
+
+    #[test]
+    fn test_push_adaptive() {
+        let buffer = AtomicRingBufferSpsc::<i32, 16>::new();
+        //defaults to a watermark of 12, nothing is dropped up to it
+        for i in 0..13 {
+            assert_eq!(buffer.push_adaptive(i), PushOutcome::Accepted);
+        }
+
+        let mut outcomes = Vec::new();
+        for i in 13..20 {
+            outcomes.push((buffer.len(), buffer.push_adaptive(i)));
+        }
+        assert_eq!(
+            outcomes,
+            [
+                (13, PushOutcome::Accepted),
+                (14, PushOutcome::DroppedProactive(14)),
+                (14, PushOutcome::Accepted),
+                (15, PushOutcome::DroppedProactive(16)),
+                (15, PushOutcome::Accepted),
+                (16, PushOutcome::DroppedProactive(18)),
+                (16, PushOutcome::DroppedFull(19)),
+            ]
+        );
+
+        //back under the watermark the count starts over
+        while buffer.len() > 10 {
+            buffer.pop();
+        }
+        buffer.set_adaptive_drop(10, 1);
+        assert_eq!(buffer.push_adaptive(20), PushOutcome::Accepted);
+        assert_eq!(buffer.push_adaptive(21), PushOutcome::DroppedProactive(21));
+    }
+
+    #[test]
+    fn test_push_adaptive_overload() {
+        let buffer = AtomicRingBufferSpsc::<usize, 16>::new();
+        buffer.set_adaptive_drop(8, 2);
+
+        //the producer pushes twice for every pop
+        let (mut next, mut last, mut proactive, mut highest) = (0, None, 0, 0);
+        for _ in 0..1000 {
+            for _ in 0..2 {
+                let occupancy = buffer.len();
+                highest = highest.max(occupancy);
+                match buffer.push_adaptive(next) {
+                    PushOutcome::Accepted => {}
+                    PushOutcome::DroppedProactive(_) => {
+                        assert!(occupancy > 8);
+                        proactive += 1;
+                    }
+                    PushOutcome::DroppedFull(_) => panic!("saturated at {occupancy}"),
+                }
+                next += 1;
+            }
+            let value = buffer.pop().unwrap();
+            assert!(last < Some(value));
+            last = Some(value);
+        }
+
+        assert!(proactive > 900);
+        assert!(highest.max(buffer.len()) < 16);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_empty_poll_ratio() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        assert_eq!(buffer.empty_poll_ratio(), 0.0);
+
+        for i in 0..10 {
+            assert_eq!(buffer.pop(), None);
+            buffer.push(i).unwrap();
+            assert_eq!(buffer.pop(), Some(i));
+        }
+        assert_eq!(buffer.empty_poll_ratio(), 0.5);
+
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.empty_poll_ratio() > 0.5);
+    }
+
+    #[test]
+    fn test_tickets() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        let mut tickets = Vec::new();
+        for round in 0..3 {
+            for i in 0..4 {
+                tickets.push(buffer.push_ticketed(round * 4 + i).unwrap());
+            }
+            assert_eq!(buffer.push_ticketed(99), Err(TryPushError::Full(99)));
+            for i in 0..4 {
+                let (ticket, value) = buffer.pop_ticketed().unwrap();
+                assert_eq!(value, round * 4 + i);
+                assert_eq!(ticket, tickets[value as usize]);
+                assert_eq!(ticket.seq, value as usize);
+            }
+        }
+        assert_eq!(buffer.pop_ticketed(), None);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        buffer.check_invariants();
+        for i in 0..10 {
+            buffer.push(i).unwrap();
+            buffer.check_invariants();
+            if i % 3 == 0 {
+                buffer.pop();
+                buffer.check_invariants();
+            }
+            if buffer.len() == 4 {
+                buffer.pop();
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "more than N")]
+    fn test_check_invariants_catches_corruption() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        buffer.head.store(5, Ordering::Relaxed);
+        buffer.check_invariants();
+    }
+
+    #[test]
+    fn test_pop_window() {
+        let buffer = AtomicRingBufferSpsc::<String, 8>::new();
+        for i in 0..6 {
+            buffer.push(i.to_string()).unwrap();
+        }
+        assert_eq!(buffer.pop_window::<7>(), None);
+        assert_eq!(buffer.len(), 6);
+
+        let window: [String; 4] = buffer.pop_window().unwrap();
+        assert_eq!(window, ["0", "1", "2", "3"]);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_window::<4>(), None);
+
+        for i in 6..10 {
+            buffer.push(i.to_string()).unwrap();
+        }
+        //wraps around the end of the buffer
+        assert_eq!(
+            buffer.pop_window::<6>().unwrap(),
+            ["4", "5", "6", "7", "8", "9"]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_lossy_quiescent() {
+        let buffer = AtomicRingBufferSpsc::<u32, 4>::new();
+        let mut out = Vec::new();
+        buffer.snapshot_lossy(&mut out);
+        assert!(out.is_empty());
+
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        buffer.pop();
+        buffer.pop();
+        buffer.push(4).unwrap();
+        buffer.snapshot_lossy(&mut out);
+        assert_eq!(out, vec![2, 3, 4]);
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_lossy_never_torn() {
+        let buffer = AtomicRingBufferSpsc::<[usize; 8], 4>::new();
+        let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let worker = thread::spawn({
+            let buffer = buffer.clone();
+            let running = running.clone();
+            move || {
+                let mut i = 0;
+                while running.load(Ordering::Relaxed) {
+                    let _ = buffer.push([i; 8]);
+                    buffer.pop();
+                    i += 1;
+                }
+            }
+        });
+
+        let mut out = Vec::new();
+        for _ in 0..10_000 {
+            out.clear();
+            buffer.snapshot_lossy(&mut out);
+            //every word of an item is the same number, a torn copy would mix two items
+            assert!(
+                out.iter()
+                    .all(|item| item.iter().all(|&word| word == item[0]))
+            );
+        }
+        running.store(false, Ordering::Relaxed);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_push_slice_streaming_publishes_per_chunk() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        ///Runs the hook whenever it is cloned, so the test can act as the consumer
+        ///in the middle of a push
+        struct Probe(usize, Rc<dyn Fn()>);
+
+        impl Clone for Probe {
+            fn clone(&self) -> Self {
+                (self.1)();
+                Probe(self.0, self.1.clone())
+            }
+        }
+
+        let buffer = AtomicRingBufferSpsc::<Probe, 64>::new();
+        let popped = Rc::new(RefCell::new(Vec::new()));
+        let hook: Rc<dyn Fn()> = {
+            let buffer = buffer.clone();
+            let popped = popped.clone();
+            Rc::new(move || {
+                while let Some(probe) = buffer.pop() {
+                    popped.borrow_mut().push(probe.0);
+                }
+            })
+        };
+
+        let values: Vec<Probe> = (0..32).map(|i| Probe(i, hook.clone())).collect();
+        assert_eq!(buffer.push_slice_streaming(&values, 8), 32);
+
+        //everything but the last chunk was visible while the push was still running
+        assert_eq!(*popped.borrow(), (0..24).collect::<Vec<_>>());
+        let rest: Vec<usize> = std::iter::from_fn(|| buffer.pop().map(|p| p.0)).collect();
+        assert_eq!(rest, (24..32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_slice_streaming_stops_when_full() {
+        let buffer = AtomicRingBufferSpsc::<u32, 8>::new();
+        buffer.push(0).unwrap();
+        let values: Vec<u32> = (1..=10).collect();
+
+        assert_eq!(buffer.push_slice_streaming(&values, 3), 7);
+        assert_eq!(buffer.len(), 8);
+        for i in 0..8 {
+            assert_eq!(buffer.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_read_cow_borrows_until_owned() {
+        static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq)]
+        struct Large(Vec<u8>);
+
+        impl Clone for Large {
+            fn clone(&self) -> Self {
+                CLONES.fetch_add(1, Ordering::Relaxed);
+                Large(self.0.clone())
+            }
+        }
+
+        let buffer = AtomicRingBufferSpsc::<Large, 4>::new();
+        let (producer, mut consumer) = AtomicRingBufferSpsc::split(buffer).unwrap();
+        assert!(consumer.read_cow().is_none());
+        producer.push(Large(vec![1; 1024])).unwrap();
+        producer.push(Large(vec![2; 1024])).unwrap();
+
+        let front = consumer.read_cow().unwrap();
+        assert!(matches!(front, Cow::Borrowed(_)));
+        assert_eq!(front.0[0], 1);
+        assert_eq!(CLONES.load(Ordering::Relaxed), 0);
+
+        let owned = front.into_owned();
+        assert_eq!(CLONES.load(Ordering::Relaxed), 1);
+        assert_eq!(consumer.len(), 2);
+
+        assert_eq!(consumer.pop(), Some(owned));
+        assert_eq!(consumer.read_cow().unwrap().0[0], 2);
+        assert_eq!(CLONES.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_drain_reuse_keeps_capacity() {
+        let buffer = AtomicRingBufferSpsc::<String, 8>::new();
+        let mut into = PopBuffer::new();
+
+        buffer.drain_reuse(&mut into);
+        assert!(into.is_empty());
+
+        for i in 0..8 {
+            buffer.push(i.to_string()).unwrap();
+        }
+        buffer.drain_reuse(&mut into);
+        assert_eq!(into.as_slice(), ["0", "1", "2", "3", "4", "5", "6", "7"]);
+        let capacity = into.capacity();
+        let ptr = into.as_ptr();
+
+        for round in 0..5 {
+            //moves the tail around so later drains wrap
+            for i in 0..(3 + round) {
+                buffer.push(format!("{round}-{i}")).unwrap();
+            }
+            buffer.drain_reuse(&mut into);
+            let expected: Vec<String> = (0..(3 + round)).map(|i| format!("{round}-{i}")).collect();
+            assert_eq!(into.as_slice(), expected.as_slice());
+            assert_eq!(into.capacity(), capacity);
+            assert_eq!(into.as_ptr(), ptr);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn test_dump_log_across_threads() {
+        use crate::Op;
+
+        let buffer = AtomicRingBufferSpsc::<u32, 2>::new();
+        let producer = thread::spawn({
+            let buffer = buffer.clone();
+            move || {
+                for i in 0..5 {
+                    while buffer.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+                thread::current().id()
+            }
+        });
+        for i in 0..5 {
+            loop {
+                if let Some(value) = buffer.pop() {
+                    assert_eq!(value, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        let producer_id = producer.join().unwrap();
+
+        let log = buffer.dump_log();
+        let pushes: Vec<usize> = log
+            .iter()
+            .filter(|r| r.op == Op::Push)
+            .map(|r| {
+                assert_eq!(r.thread, producer_id);
+                r.seq
+            })
+            .collect();
+        let pops: Vec<usize> = log
+            .iter()
+            .filter(|r| r.op == Op::Pop)
+            .map(|r| {
+                assert_eq!(r.thread, thread::current().id());
+                r.seq
+            })
+            .collect();
+        assert_eq!(pushes, vec![0, 1, 2, 3, 4]);
+        assert_eq!(pops, vec![0, 1, 2, 3, 4]);
+
+        //replaying the log single threaded reproduces the run
+        let replay = AtomicRingBufferSpsc::<u32, 2>::new();
+        for record in &log {
+            match record.op {
+                Op::Push => replay.push(record.seq as u32).unwrap(),
+                Op::Pop => assert_eq!(replay.pop(), Some(record.seq as u32)),
+            }
+        }
+        assert!(replay.is_empty());
+    }
+
+    #[test]
+    fn test_push_coalesce() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        enum Event {
+            A,
+            B,
+        }
+
+        let buffer = AtomicRingBufferSpsc::<Event, 8>::new();
+        assert_eq!(buffer.push_coalesce(Event::A), Ok(true));
+        assert_eq!(buffer.push_coalesce(Event::A), Ok(false));
+        assert_eq!(buffer.push_coalesce(Event::B), Ok(true));
+        assert_eq!(buffer.push_coalesce(Event::B), Ok(false));
+        assert_eq!(buffer.push_coalesce(Event::A), Ok(true));
+
+        assert_eq!(buffer.pop(), Some(Event::A));
+        assert_eq!(buffer.pop(), Some(Event::B));
+        assert_eq!(buffer.pop(), Some(Event::A));
+        assert_eq!(buffer.pop(), None);
+
+        //once read, an equal item is news again
+        assert_eq!(buffer.push_coalesce(Event::A), Ok(true));
+        assert_eq!(buffer.pop(), Some(Event::A));
+    }
+
+    #[test]
+    fn test_inline_and_heap_construction_match() {
+        fn exercise(buffer: Arc<AtomicRingBufferSpsc<String, 4>>) -> Vec<String> {
+            let mut seen = Vec::new();
+            for round in 0..3 {
+                for i in 0..4 {
+                    buffer.push(format!("{round}-{i}")).unwrap();
+                }
+                assert!(buffer.push("full".into()).is_err());
+                seen.extend(buffer.pop_window::<2>().unwrap());
+                seen.extend(std::iter::from_fn(|| buffer.pop()));
+            }
+            buffer.push("left behind".into()).unwrap();
+            seen
+        }
+
+        let inline = exercise(AtomicRingBufferSpsc::new_inline());
+        let heap = exercise(AtomicRingBufferSpsc::new_on_heap());
+        assert_eq!(inline, heap);
+        assert_eq!(inline.len(), 12);
+    }
+
+    #[test]
+    fn test_large_buffer_is_built_on_the_heap() {
+        //8MiB of slots, several times the stack of a test thread
+        let buffer = AtomicRingBufferSpsc::<u64, { 1 << 20 }>::new();
+        for i in 0..10 {
+            buffer.push(i).unwrap();
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.pop(), Some(0));
+        assert_eq!(buffer.capacity(), 1 << 20);
+    }
+
+    #[test]
+    fn test_read_transaction_rollback_and_commit() {
+        let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+        let (producer, mut consumer) = AtomicRingBufferSpsc::split(buffer).unwrap();
+        for s in ["a", "b", "c"] {
+            producer.push(s.to_string()).unwrap();
+        }
+
+        {
+            let read = consumer.begin_read();
+            let first = read.take().unwrap();
+            let second = read.take().unwrap();
+            assert_eq!((first.as_str(), second.as_str()), ("a", "b"));
+            assert_eq!(read.taken(), 2);
+        }
+        assert_eq!(consumer.len(), 3);
+
+        let read = consumer.begin_read();
+        assert_eq!(read.take().map(String::as_str), Some("a"));
+        assert_eq!(read.take().map(String::as_str), Some("b"));
+        assert_eq!(read.take().map(String::as_str), Some("c"));
+        assert_eq!(read.take(), None);
+        producer.push("d".to_string()).unwrap();
+        assert_eq!(read.take().map(String::as_str), Some("d"));
+        read.commit();
+        assert!(consumer.is_empty());
+
+        let read = consumer.begin_read();
+        assert_eq!(read.take(), None);
+        read.commit();
+        producer.push("e".to_string()).unwrap();
+        let read = consumer.begin_read();
+        read.take();
+        read.commit();
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[cfg(feature = "test-alloc")]
+    #[test]
+    fn test_hot_path_does_not_allocate() {
+        use crate::test_alloc::allocations_during;
+
+        let buffer = AtomicRingBufferSpsc::<u64, 8>::new();
+        let mut into = PopBuffer::with_capacity(8);
+        let allocations = allocations_during(|| {
+            for round in 0..100 {
+                for i in 0..6 {
+                    buffer.push(round * 6 + i).unwrap();
+                }
+                assert!(buffer.pop().is_some());
+                assert!(buffer.pop_window::<2>().is_some());
+                buffer.drain_reuse(&mut into);
+                assert_eq!(into.len(), 3);
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_peek_with() {
+        let buffer = AtomicRingBufferSpsc::<Vec<u8>, 4>::new();
+        assert_eq!(buffer.peek_with(|v| v.len()), None);
+
+        buffer.push(vec![1, 2, 3]).unwrap();
+        buffer.push(vec![4]).unwrap();
+        assert_eq!(buffer.peek_with(|v| v.len()), Some(3));
+        assert_eq!(buffer.peek_with(|v| v[0]), Some(1));
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.pop(), Some(vec![1, 2, 3]));
+        assert_eq!(buffer.peek_with(|v| v.clone()), Some(vec![4]));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_pop_cooperative() {
+        let buffer = AtomicRingBufferSpsc::<i32, 2>::new();
+        let producer = tokio::spawn({
+            let buffer = buffer.clone();
+            async move {
+                tokio::task::yield_now().await;
+                buffer.push(3).unwrap();
+            }
+        });
+
+        assert_eq!(buffer.pop_cooperative().await, Some(3));
+        producer.await.unwrap();
+    }
+
+    #[test]
+    fn test_into_mpmc() {
+        let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+        //wrapped, so the items sit in slots 3, 0 and 1
+        for i in 0..3 {
+            buffer.push(i.to_string()).unwrap();
+            buffer.pop();
+        }
+        for word in ["a", "b", "c"] {
+            buffer.push(word.to_string()).unwrap();
+        }
+
+        let other = buffer.clone();
+        let Err(buffer) = AtomicRingBufferSpsc::into_mpmc(buffer) else {
+            panic!("converted a shared buffer");
+        };
+        drop(other);
+        assert_eq!(buffer.len(), 3);
+
+        let Ok(mpmc) = AtomicRingBufferSpsc::into_mpmc(buffer) else {
+            panic!("an unshared buffer should convert");
+        };
+        assert_eq!(mpmc.len(), 3);
+
+        //several consumers share it now, the items still come out in push order
+        let order = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let mpmc = mpmc.clone();
+                let order = order.clone();
+                thread::spawn(move || {
+                    let mut order = order.lock();
+                    if let Some(value) = mpmc.pop() {
+                        order.push(value);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*order.lock(), ["a", "b", "c"]);
+        assert!(mpmc.is_empty());
+        assert!(mpmc.push("d".to_string()).is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_recv_woken_by_push() {
+        let buffer = AtomicRingBufferSpsc::<usize, 4>::new();
+        let (producer, consumer) = AtomicRingBufferSpsc::split(buffer).unwrap();
+
+        //a plain thread, so the only way the task gets polled again is the waker
+        let handle = thread::spawn(move || {
+            for i in 0..1000 {
+                if i % 100 == 0 {
+                    thread::sleep(std::time::Duration::from_millis(1));
+                }
+                while producer.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        for i in 0..1000 {
+            assert_eq!(consumer.recv().await, i);
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_deliver_redeliver_ack() {
+        let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+        for s in ["a", "b", "c", "d"] {
+            buffer.push(s.to_string()).unwrap();
+        }
+
+        assert_eq!(buffer.deliver().as_deref(), Some("a"));
+        assert_eq!(buffer.deliver().as_deref(), Some("b"));
+        assert_eq!(buffer.unacked(), 2);
+
+        buffer.redeliver();
+        assert_eq!(buffer.unacked(), 0);
+        let all: Vec<String> = std::iter::from_fn(|| buffer.deliver()).collect();
+        assert_eq!(all, ["a", "b", "c", "d"]);
+
+        //delivered but unacked items still take up their slots
+        assert!(buffer.push("e".to_string()).is_err());
+        assert_eq!(buffer.len(), 4);
+
+        assert_eq!(buffer.ack(2), 2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.unacked(), 2);
+        buffer.push("e".to_string()).unwrap();
+        buffer.push("f".to_string()).unwrap();
+
+        buffer.redeliver();
+        assert_eq!(buffer.deliver().as_deref(), Some("c"));
+        assert_eq!(buffer.ack(10), 1);
+        assert_eq!(buffer.pop().as_deref(), Some("d"));
+        assert_eq!(buffer.deliver().as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn test_unacked_from_another_thread() {
+        let buffer = AtomicRingBufferSpsc::<u32, 4>::new();
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.deliver();
+
+        let monitor = buffer.clone();
+        assert_eq!(thread::spawn(move || monitor.unacked()).join().unwrap(), 1);
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn test_ack_is_recorded_like_pop() {
+        use crate::Op;
+
+        let buffer = AtomicRingBufferSpsc::<u32, 4>::new();
+        for i in 0..3 {
+            buffer.push(i).unwrap();
+        }
+        buffer.deliver();
+        buffer.deliver();
+        buffer.ack(2);
+
+        let pops: Vec<usize> = buffer
+            .dump_log()
+            .iter()
+            .filter(|record| record.op == Op::Pop)
+            .map(|record| record.seq)
+            .collect();
+        assert_eq!(pops, [0, 1]);
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn test_batch_ops_are_recorded_per_item() {
+        use crate::Op;
+
+        let mut buffer = AtomicRingBufferSpsc::<u32, 8>::new();
+        assert_eq!(buffer.push_slice(&[0, 1, 2]), 3);
+        assert_eq!(buffer.push_slice_clone(&[3, 4]), 2);
+        assert_eq!(buffer.push_slice_streaming(&[5, 6, 7], 2), 3);
+        let mut out = [0; 2];
+        assert_eq!(buffer.pop_slice(&mut out), 2);
+        assert_eq!(buffer.pop_window::<2>(), Some([2, 3]));
+        let mut into = PopBuffer::new();
+        buffer.drain_reuse(&mut into);
+        assert_eq!(into.as_slice(), [4, 5, 6, 7]);
+        buffer.push_slice(&[8, 9]);
+        Arc::get_mut(&mut buffer).unwrap().clear();
+
+        let log = buffer.dump_log();
+        let ops: Vec<(Op, usize)> = log.iter().map(|r| (r.op, r.seq)).collect();
+        let pushes = (0..10).map(|seq| (Op::Push, seq));
+        let pops = (0..10).map(|seq| (Op::Pop, seq));
+        let expected: Vec<_> = pushes
+            .clone()
+            .take(8)
+            .chain(pops.clone().take(8))
+            .chain(pushes.skip(8))
+            .chain(pops.skip(8))
+            .collect();
+        assert_eq!(ops, expected);
+    }
+
+    #[test]
+    fn test_exists_counts_tail_slot() {
+        let buffer = AtomicRingBufferSpsc::<usize, 4>::new();
+        assert!((0..4).all(|i| !buffer.exists(i)));
+
+        buffer.push(0).unwrap();
+        buffer.push(1).unwrap();
+        buffer.pop();
+        //tail 1, head 2
+        let occupied: Vec<bool> = (0..4).map(|i| buffer.exists(i)).collect();
+        assert_eq!(occupied, [false, true, false, false]);
+
+        for i in 2..5 {
+            buffer.push(i).unwrap();
+        }
+        //wrapped and full, tail 1, head 5
+        assert!((0..4).all(|i| buffer.exists(i)));
+
+        buffer.pop();
+        buffer.pop();
+        //wrapped, tail 3, head 5
+        let occupied: Vec<bool> = (0..4).map(|i| buffer.exists(i)).collect();
+        assert_eq!(occupied, [true, false, false, true]);
+    }
+
+    #[test]
+    fn test_push_slice_clone_strings() {
+        let buffer = AtomicRingBufferSpsc::<String, 4>::new();
+        let values: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(buffer.push_slice_clone(&values), 3);
+        assert_eq!(buffer.push_slice_clone(&values), 1);
+        assert_eq!(buffer.push_slice_clone(&values), 0);
+
+        let popped: Vec<String> = std::iter::from_fn(|| buffer.pop()).collect();
+        assert_eq!(popped, ["a", "b", "c", "a"]);
+        assert_eq!(values.len(), 3);
+    }
+
+    #[test]
+    fn test_push_slice_clone_panic_publishes_prefix() {
+        #[derive(Debug, PartialEq)]
+        struct Fragile(usize);
+        impl Clone for Fragile {
+            fn clone(&self) -> Self {
+                assert!(self.0 != 2, "clone failed");
+                Fragile(self.0)
+            }
+        }
+
+        let buffer = AtomicRingBufferSpsc::<Fragile, 8>::new();
+        let values: Vec<Fragile> = (0..4).map(Fragile).collect();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            buffer.push_slice_clone(&values)
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(buffer.len(), 2);
+        #[cfg(feature = "metrics")]
+        assert_eq!(buffer.max_lag_since_reset(), 2);
+        assert_eq!(buffer.pop(), Some(Fragile(0)));
+        assert_eq!(buffer.pop(), Some(Fragile(1)));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_split_handles() {
+        let (producer, mut consumer) =
+            AtomicRingBufferSpsc::split(AtomicRingBufferSpsc::<String, 4>::new()).unwrap();
+
+        let handle = std::thread::spawn(move || {
+            for i in 0..100 {
+                let mut value = i.to_string();
+                while let Err(back) = producer.push(value) {
+                    value = back.into_inner();
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut next = 0;
+        while next < 100 {
+            if let Some(peeked) = consumer.peek() {
+                assert_eq!(consumer.peek_with(String::len), Some(peeked.len()));
+                assert_eq!(consumer.pop(), Some(peeked));
+                next += 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+        handle.join().unwrap();
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_split_needs_unique_buffer() {
+        let buffer = AtomicRingBufferSpsc::<i32, 4>::new();
+        let other = buffer.clone();
+        let Err(buffer) = AtomicRingBufferSpsc::split(buffer) else {
+            panic!("split a shared buffer");
+        };
+        assert!(buffer.push(1).is_ok());
+
+        drop(other);
+        let weak = Arc::downgrade(&buffer);
+        let Err(buffer) = AtomicRingBufferSpsc::split(buffer) else {
+            panic!("split a buffer with a weak handle");
+        };
+
+        drop(weak);
+        let (producer, consumer) = AtomicRingBufferSpsc::split(buffer).unwrap();
+        assert!(producer.push(2).is_ok());
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_push_slice_wraps() {
+        let buffer = AtomicRingBufferSpsc::<u8, 8>::new();
+        assert_eq!(buffer.push_slice(&[]), 0);
+        assert_eq!(buffer.push_slice(&[0, 1, 2, 3, 4, 5]), 6);
+        for expected in 0..5 {
+            assert_eq!(buffer.pop(), Some(expected));
+        }
+
+        //starts at slot 6 and wraps around to slot 0
+        let data: Vec<u8> = (10..20).collect();
+        assert_eq!(buffer.push_slice(&data), 7);
+        assert_eq!(buffer.push_slice(&data), 0);
+        assert_eq!(buffer.len(), 8);
+
+        let popped: Vec<u8> = std::iter::from_fn(|| buffer.pop()).collect();
+        assert_eq!(popped, [5, 10, 11, 12, 13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn test_pop_slice_wraps_and_keeps_rest() {
+        let buffer = AtomicRingBufferSpsc::<u8, 8>::new();
+        let mut out = [0u8; 16];
+        assert_eq!(buffer.pop_slice(&mut out), 0);
+
+        buffer.push_slice(&[0, 1, 2, 3, 4, 5]);
+        assert_eq!(buffer.pop_slice(&mut out[..5]), 5);
+        assert_eq!(out[..5], [0, 1, 2, 3, 4]);
+
+        //occupies slots 5, 6, 7, 0, 1, 2
+        buffer.push_slice(&[6, 7, 8, 9, 10]);
+        assert_eq!(buffer.pop_slice(&mut out[..4]), 4);
+        assert_eq!(out[..4], [5, 6, 7, 8]);
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.pop_slice(&mut out), 2);
+        assert_eq!(out[..2], [9, 10]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_byte_pipe_across_threads() {
+        let buffer = AtomicRingBufferSpsc::<u8, 64>::new();
+        let producer = buffer.clone();
+        let data: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let expected = data.clone();
+
+        let handle = std::thread::spawn(move || {
+            let mut sent = 0;
+            while sent < data.len() {
+                let pushed = producer.push_slice(&data[sent..]);
+                if pushed == 0 {
+                    std::thread::yield_now();
+                }
+                sent += pushed;
+            }
+        });
+
+        let mut received = Vec::new();
+        let mut out = [0u8; 48];
+        while received.len() < expected.len() {
+            let popped = buffer.pop_slice(&mut out);
+            if popped == 0 {
+                std::thread::yield_now();
+            }
+            received.extend_from_slice(&out[..popped]);
+        }
+        handle.join().unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_next_seqs_match_tickets() {
+        let buffer = AtomicRingBufferSpsc::<usize, 4>::new();
+        for i in 0..10 {
+            let seq = buffer.next_push_seq();
+            assert_eq!(seq, i);
+            assert_eq!(buffer.push_ticketed(i).unwrap().seq, seq);
+            assert_eq!(buffer.next_push_seq(), seq + 1);
+
+            let seq = buffer.next_pop_seq();
+            assert_eq!(buffer.pop_ticketed().unwrap().0.seq, seq);
+        }
+
+        buffer.fill_to(4);
+        let seq = buffer.next_push_seq();
+        assert!(buffer.push(0).is_err());
+        assert_eq!(buffer.next_push_seq(), seq);
+    }
+
+    #[cfg(feature = "timing")]
+    #[test]
+    fn test_pop_timed_residency() {
+        let buffer = AtomicRingBufferSpsc::<usize, 4>::new();
+        assert!(buffer.pop_timed().is_none());
+
+        buffer.push(1).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        buffer.push_slice(&[2, 3]);
+
+        let (value, residency) = buffer.pop_timed().unwrap();
+        assert_eq!(value, 1);
+        assert!(residency >= Duration::from_millis(20), "{residency:?}");
+
+        thread::sleep(Duration::from_millis(5));
+        let (value, residency) = buffer.pop_timed().unwrap();
+        assert_eq!(value, 2);
+        assert!(residency >= Duration::from_millis(5), "{residency:?}");
+        assert!(residency < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_would_accept() {
+        let buffer = AtomicRingBufferSpsc::<usize, 4>::new();
+        assert!(buffer.would_accept());
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert!(!buffer.would_accept());
+        buffer.pop();
+        assert!(buffer.would_accept());
+    }
 }
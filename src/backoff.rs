@@ -0,0 +1,135 @@
+use core::hint;
+#[cfg(feature = "std")]
+use std::thread;
+
+const SPIN_LIMIT: u32 = 6;
+const YIELD_LIMIT: u32 = 10;
+
+///An exponential backoff, the same one the atomic buffers use internally.
+///Use it to build your own retry loops!
+///
+///```
+///use lockless_datastructures::{AtomicRingBufferMpmc, Backoff};
+///
+///let buffer = AtomicRingBufferMpmc::<u32, 2>::new();
+///let mut backoff = Backoff::new();
+///let mut value = 7;
+///loop {
+///    match buffer.push(value) {
+///        Ok(()) => break,
+///        Err(err) if backoff.is_completed() => {
+///            //Give up spinning, a real producer could park here instead
+///            value = err.into_inner();
+///            std::thread::yield_now();
+///            backoff.reset();
+///        }
+///        Err(err) => {
+///            value = err.into_inner();
+///            backoff.snooze();
+///        }
+///    }
+///}
+///assert_eq!(buffer.pop(), Some(7));
+///```
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    step: u32,
+    spin_limit: u32,
+    yield_limit: u32,
+}
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Backoff {
+    ///Spins for 7 steps and yields until step 10, what the buffers use
+    pub fn new() -> Self {
+        Self::with_limits(SPIN_LIMIT, YIELD_LIMIT)
+    }
+
+    ///Spins `2^step` times for steps up to `spin_limit`, then yields the thread each
+    ///step until `yield_limit` is passed and `is_completed` turns true. Lower limits
+    ///give up the CPU sooner, better for throughput when threads outnumber cores,
+    ///higher ones keep spinning for lower latency. Panics if `spin_limit` is 32 or more
+    pub fn with_limits(spin_limit: u32, yield_limit: u32) -> Self {
+        assert!(
+            spin_limit < u32::BITS,
+            "Spin limit {spin_limit} would spin 2^{spin_limit} times"
+        );
+        Self {
+            step: 0,
+            spin_limit,
+            yield_limit,
+        }
+    }
+
+    ///Call this where you want to backoff!
+    ///Spins for the first few calls and then starts yielding the thread.
+    ///Without `std` there is no thread to yield, so it keeps spinning at the longest step
+    #[inline]
+    pub fn snooze(&mut self) {
+        if self.step <= self.spin_limit {
+            for _ in 0..(1u32 << self.step) {
+                hint::spin_loop();
+            }
+        } else {
+            #[cfg(feature = "std")]
+            thread::yield_now();
+            #[cfg(not(feature = "std"))]
+            for _ in 0..(1u32 << self.spin_limit) {
+                hint::spin_loop();
+            }
+        }
+
+        if self.step <= self.yield_limit {
+            self.step += 1;
+        }
+    }
+
+    ///Starts the backoff over from the shortest spin
+    #[inline]
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    ///Returns true once backing off has stopped helping and blocking the thread is advised
+    #[inline]
+    pub fn is_completed(&self) -> bool {
+        self.step > self.yield_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snoozes_until_completed(mut backoff: Backoff) -> usize {
+        let mut snoozes = 0;
+        while !backoff.is_completed() {
+            backoff.snooze();
+            snoozes += 1;
+        }
+        snoozes
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(snoozes_until_completed(Backoff::new()), 11);
+        assert_eq!(snoozes_until_completed(Backoff::with_limits(2, 4)), 5);
+        //no yielding at all, completed right after the spins
+        assert_eq!(snoozes_until_completed(Backoff::with_limits(3, 3)), 4);
+
+        let mut backoff = Backoff::with_limits(0, 0);
+        backoff.snooze();
+        assert!(backoff.is_completed());
+        backoff.reset();
+        assert!(!backoff.is_completed());
+    }
+
+    #[test]
+    #[should_panic(expected = "Spin limit 32")]
+    fn test_spin_limit_too_large() {
+        Backoff::with_limits(32, 40);
+    }
+}
@@ -0,0 +1,283 @@
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, fence};
+
+use crate::Padded;
+use crate::ordering::{ACQUIRE, RELAXED, RELEASE};
+use crate::primitives::Arc;
+
+///A slot stamped seqlock style: `2 * pos + 1` while the value for position `pos` is being
+///written, `2 * pos + 2` once it is complete, 0 before the first write
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+///A ring buffer where every subscriber sees every item, instead of each item going to one
+///consumer. There is one [`Publisher`] and any number of subscribers, each with its own
+///cursor. The publisher never waits for subscribers: it always overwrites the oldest slot,
+///so a subscriber lagging more than N items behind misses the overwritten ones, counted by
+///[`Subscriber::missed`].
+///
+///It is lock free, nobody waits on anybody. Subscribers copy a slot out and then check
+///its stamp to see whether the publisher started overwriting it mid copy, in which case
+///the copy is thrown away. That is why `T` has to be `Copy`: a value that may be getting
+///overwritten can be copied bit by bit and discarded, but not cloned
+///
+///```
+///use lockless_datastructures::BroadcastRingBuffer;
+///
+///let buffer = BroadcastRingBuffer::<u32, 4>::new();
+///let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+///let mut first = BroadcastRingBuffer::subscribe(&buffer);
+///let mut second = BroadcastRingBuffer::subscribe(&buffer);
+///
+///publisher.push(7);
+///assert_eq!(first.pop(), Some(7));
+///assert_eq!(second.pop(), Some(7));
+///```
+pub struct BroadcastRingBuffer<T: Copy, const N: usize> {
+    head: Padded<AtomicUsize>,
+    slots: [Slot<T>; N],
+    ///Set while a `Publisher` exists, so there is only ever one
+    publisher_claimed: AtomicBool,
+}
+unsafe impl<T: Copy + Send, const N: usize> Sync for BroadcastRingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> BroadcastRingBuffer<T, N> {
+    ///Masking a position with this wraps it onto its slot, see `AtomicRingBufferSpsc::MASK`
+    const MASK: usize = N - 1;
+
+    pub fn new() -> Arc<Self> {
+        const {
+            assert!(
+                N != 0 && N.is_power_of_two(),
+                "Buffer size N must be a power of two"
+            )
+        };
+        Arc::new(Self {
+            head: Padded(AtomicUsize::new(0)),
+            slots: std::array::from_fn(|_| Slot {
+                stamp: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            publisher_claimed: AtomicBool::new(false),
+        })
+    }
+
+    ///The handle that pushes, None while another one is alive. Dropping it lets the next
+    ///call claim a new one
+    pub fn publisher(this: &Arc<Self>) -> Option<Publisher<T, N>> {
+        if this.publisher_claimed.swap(true, ACQUIRE) {
+            return None;
+        }
+        Some(Publisher {
+            buffer: this.clone(),
+            _not_sync: PhantomData,
+        })
+    }
+
+    ///A subscriber that sees the items pushed from now on
+    pub fn subscribe(this: &Arc<Self>) -> Subscriber<T, N> {
+        Subscriber {
+            cursor: this.head.load(ACQUIRE),
+            missed: 0,
+            buffer: this.clone(),
+        }
+    }
+}
+
+///The single pushing end of a [`BroadcastRingBuffer`], see
+///[`BroadcastRingBuffer::publisher`]. It can be sent to another thread but not shared
+pub struct Publisher<T: Copy, const N: usize> {
+    buffer: Arc<BroadcastRingBuffer<T, N>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T: Copy, const N: usize> Publisher<T, N> {
+    ///Publishes `value` to every subscriber, overwriting the oldest item if the buffer is
+    ///full. Never fails
+    pub fn push(&self, value: T) {
+        let buffer = &*self.buffer;
+        let head = buffer.head.load(RELAXED);
+        let slot = &buffer.slots[head & BroadcastRingBuffer::<T, N>::MASK];
+
+        slot.stamp
+            .store(head.wrapping_mul(2).wrapping_add(1), RELAXED);
+        //a subscriber that sees any of the new bytes also sees the odd stamp
+        fence(RELEASE);
+        unsafe { (*slot.value.get()).write(value) };
+        slot.stamp
+            .store(head.wrapping_mul(2).wrapping_add(2), RELEASE);
+        buffer.head.store(head.wrapping_add(1), RELEASE);
+    }
+}
+
+impl<T: Copy, const N: usize> Drop for Publisher<T, N> {
+    fn drop(&mut self) {
+        self.buffer.publisher_claimed.store(false, RELEASE);
+    }
+}
+
+///One reader of a [`BroadcastRingBuffer`], see [`BroadcastRingBuffer::subscribe`]
+pub struct Subscriber<T: Copy, const N: usize> {
+    buffer: Arc<BroadcastRingBuffer<T, N>>,
+    cursor: usize,
+    missed: usize,
+}
+
+impl<T: Copy, const N: usize> Subscriber<T, N> {
+    ///Copies out the next item this subscriber has not seen. If the publisher overwrote
+    ///some of them already it skips ahead to the oldest one still buffered
+    pub fn pop(&mut self) -> Option<T> {
+        loop {
+            let head = self.buffer.head.load(ACQUIRE);
+            if self.cursor == head {
+                return None;
+            }
+            if head.wrapping_sub(self.cursor) > N {
+                self.skip_to(head.wrapping_sub(N));
+            }
+
+            let slot = &self.buffer.slots[self.cursor & BroadcastRingBuffer::<T, N>::MASK];
+            let expected = self.cursor.wrapping_mul(2).wrapping_add(2);
+            let stamp = slot.stamp.load(ACQUIRE);
+            if stamp == expected {
+                let value = unsafe { core::ptr::read_volatile(slot.value.get()) };
+                fence(ACQUIRE);
+                if slot.stamp.load(RELAXED) == expected {
+                    self.cursor = self.cursor.wrapping_add(1);
+                    return Some(unsafe { value.assume_init() });
+                }
+                //overwritten mid copy, the copy may be torn and is never looked at
+                continue;
+            }
+
+            //the slot is being or was rewritten for position `latest` a lap or more on,
+            //so the oldest item still intact is the one right after it in the ring
+            let latest = stamp.wrapping_sub(1) / 2;
+            self.skip_to(latest.wrapping_sub(N - 1));
+        }
+    }
+
+    ///How many items were lost to overwrites before this subscriber got to them
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    ///Items pushed but not popped by this subscriber yet, including missed ones
+    pub fn lag(&self) -> usize {
+        self.buffer.head.load(ACQUIRE).wrapping_sub(self.cursor)
+    }
+
+    fn skip_to(&mut self, cursor: usize) {
+        self.missed += cursor.wrapping_sub(self.cursor);
+        self.cursor = cursor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_every_subscriber_sees_everything() {
+        let buffer = BroadcastRingBuffer::<(usize, usize), 8>::new();
+        let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+        let mut subscribers = [
+            BroadcastRingBuffer::subscribe(&buffer),
+            BroadcastRingBuffer::subscribe(&buffer),
+        ];
+
+        for round in 0..3 {
+            for i in 0..8 {
+                publisher.push((round, i));
+            }
+            for subscriber in &mut subscribers {
+                for i in 0..8 {
+                    assert_eq!(subscriber.pop(), Some((round, i)));
+                }
+                assert_eq!(subscriber.pop(), None);
+            }
+        }
+        assert!(subscribers.iter().all(|s| s.missed() == 0));
+    }
+
+    #[test]
+    fn test_lagging_subscriber_misses_oldest() {
+        let buffer = BroadcastRingBuffer::<usize, 4>::new();
+        let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+        publisher.push(100);
+        let mut fast = BroadcastRingBuffer::subscribe(&buffer);
+        let mut slow = BroadcastRingBuffer::subscribe(&buffer);
+
+        for i in 0..10 {
+            publisher.push(i);
+            assert_eq!(fast.pop(), Some(i));
+        }
+        assert_eq!(slow.lag(), 10);
+
+        //only the last 4 are still buffered
+        let seen: Vec<usize> = std::iter::from_fn(|| slow.pop()).collect();
+        assert_eq!(seen, [6, 7, 8, 9]);
+        assert_eq!(slow.missed(), 6);
+        assert_eq!(fast.missed(), 0);
+    }
+
+    #[test]
+    fn test_single_publisher() {
+        let buffer = BroadcastRingBuffer::<usize, 4>::new();
+        let mut subscriber = BroadcastRingBuffer::subscribe(&buffer);
+        let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+        assert!(BroadcastRingBuffer::publisher(&buffer).is_none());
+
+        publisher.push(1);
+        drop(publisher);
+        //the next one carries on where the last one stopped
+        let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+        publisher.push(2);
+        assert_eq!(subscriber.pop(), Some(1));
+        assert_eq!(subscriber.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_concurrent_subscribers_see_increasing_untorn_items() {
+        let buffer = BroadcastRingBuffer::<[usize; 8], 16>::new();
+        let publisher = BroadcastRingBuffer::publisher(&buffer).unwrap();
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let mut subscriber = BroadcastRingBuffer::subscribe(&buffer);
+                thread::spawn(move || {
+                    let mut last = None;
+                    let mut received = 0;
+                    while last != Some(999) {
+                        match subscriber.pop() {
+                            Some(value) => {
+                                //every word is written with the same number, a torn copy
+                                //would mix two pushes
+                                assert!(value.iter().all(|&word| word == value[0]));
+                                assert!(last.is_none_or(|last| value[0] > last));
+                                last = Some(value[0]);
+                                received += 1;
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                    assert_eq!(received + subscriber.missed(), 1000);
+                })
+            })
+            .collect();
+
+        for i in 0..1000 {
+            publisher.push([i; 8]);
+            if i % 8 == 0 {
+                thread::yield_now();
+            }
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
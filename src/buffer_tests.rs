@@ -0,0 +1,144 @@
+//!Tests for what the SPSC, MPMC and mutex ring buffers have in common, written once and
+//!run against each of them
+
+use crate::{Arc, AtomicRingBufferMpmc, AtomicRingBufferSpsc, MutexRingBuffer};
+
+///`clear` takes `&mut` on the lock free buffers, which are only reachable through our
+///Arc, and `&` on the mutex one
+trait ClearOwned {
+    fn clear_owned(&mut self);
+}
+
+impl<T, const N: usize> ClearOwned for Arc<AtomicRingBufferSpsc<T, N>> {
+    fn clear_owned(&mut self) {
+        Arc::get_mut(self).unwrap().clear();
+    }
+}
+
+impl<T, const N: usize> ClearOwned for Arc<AtomicRingBufferMpmc<T, N>> {
+    fn clear_owned(&mut self) {
+        Arc::get_mut(self).unwrap().clear();
+    }
+}
+
+impl<T, const N: usize> ClearOwned for MutexRingBuffer<T, N> {
+    fn clear_owned(&mut self) {
+        self.clear();
+    }
+}
+
+macro_rules! buffer_tests {
+    ($($(#[$attr:meta])* $module:ident: $buffer:ident,)*) => {$(
+        $(#[$attr])*
+        mod $module {
+            use super::ClearOwned;
+            use crate::$buffer;
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            #[test]
+            fn test_drop_observer() {
+                let seen = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+                {
+                    let buffer = $buffer::<String, 8>::new();
+                    let log = seen.clone();
+                    assert!(buffer.set_drop_observer(move |item: &String| {
+                        log.lock().push(item.clone())
+                    }));
+                    //the first observer stays
+                    assert!(!buffer.set_drop_observer(|_| unreachable!()));
+
+                    for word in ["a", "b", "c", "d", "e"] {
+                        buffer.push(word.to_string()).ok().unwrap();
+                    }
+                    buffer.pop();
+                    buffer.pop();
+                    assert!(seen.lock().is_empty());
+                }
+                assert_eq!(*seen.lock(), vec!["c", "d", "e"]);
+            }
+
+            #[test]
+            fn test_fill_to() {
+                let buffer = $buffer::<u32, 8>::new();
+                buffer.fill_to(5);
+                assert_eq!(buffer.len(), 5);
+                buffer.fill_to(3);
+                assert_eq!(buffer.len(), 5);
+                for _ in 0..5 {
+                    assert_eq!(buffer.pop(), Some(0));
+                }
+                assert_eq!(buffer.pop(), None);
+            }
+
+            #[test]
+            fn test_clear_drops_each_once() {
+                static DROPS: AtomicUsize = AtomicUsize::new(0);
+                struct Counted;
+                impl Drop for Counted {
+                    fn drop(&mut self) {
+                        DROPS.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                let mut buffer = $buffer::<Counted, 4>::new();
+                for _ in 0..3 {
+                    buffer.push(Counted).ok().unwrap();
+                    buffer.pop();
+                }
+                DROPS.store(0, Ordering::Relaxed);
+                //wrapped around the storage
+                for _ in 0..4 {
+                    buffer.push(Counted).ok().unwrap();
+                }
+
+                buffer.clear_owned();
+                assert_eq!(DROPS.load(Ordering::Relaxed), 4);
+                assert!(buffer.is_empty());
+                assert!(buffer.pop().is_none());
+
+                buffer.push(Counted).ok().unwrap();
+                drop(buffer);
+                assert_eq!(DROPS.load(Ordering::Relaxed), 5);
+            }
+
+            #[test]
+            fn test_wrap_count() {
+                let buffer = $buffer::<usize, 4>::new();
+                assert_eq!(buffer.wrap_count(), 0);
+                for i in 0..(2 * 4 + 3) {
+                    buffer.push(i).unwrap();
+                    buffer.pop().unwrap();
+                }
+                assert_eq!(buffer.wrap_count(), 2);
+            }
+
+            #[cfg(feature = "metrics")]
+            #[test]
+            fn test_chronically_full() {
+                let buffer = $buffer::<usize, 16>::new();
+                assert!(!buffer.is_chronically_full());
+
+                for i in 0..16 {
+                    buffer.push(i).unwrap();
+                }
+                for i in 0..500 {
+                    buffer.pop();
+                    buffer.push(i).unwrap();
+                }
+                assert!(buffer.is_chronically_full());
+
+                while buffer.pop().is_some() {}
+                for _ in 0..100 {
+                    assert!(buffer.pop().is_none());
+                }
+                assert!(!buffer.is_chronically_full());
+            }
+        }
+    )*};
+}
+
+buffer_tests! {
+    spsc: AtomicRingBufferSpsc,
+    mpmc: AtomicRingBufferMpmc,
+    mutex: MutexRingBuffer,
+}
@@ -0,0 +1,146 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+///Picks the alignment of a [`CacheAligned`], only the sizes with an [`SupportedAlignment`]
+///impl can be used
+#[derive(Debug, Clone, Copy)]
+pub struct Alignment<const A: usize>;
+
+///Implemented for the alignments `CacheAligned` supports: 32, 64, 128 and 256 bytes
+pub trait SupportedAlignment: sealed::Sealed {
+    ///Zero sized type with the alignment
+    type Aligner: Copy + Default;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! aligners {
+    ($($name:ident = $align:literal),*) => {
+        $(
+            #[doc(hidden)]
+            #[derive(Debug, Clone, Copy, Default)]
+            #[repr(align($align))]
+            pub struct $name;
+
+            impl sealed::Sealed for Alignment<$align> {}
+            impl SupportedAlignment for Alignment<$align> {
+                type Aligner = $name;
+            }
+        )*
+    };
+}
+
+aligners!(Align32 = 32, Align64 = 64, Align128 = 128, Align256 = 256);
+
+///Like [`Padded`](crate::Padded) but the alignment is a parameter, so `T` gets a whole
+///cache line to itself on chips whose lines aren't 64 bytes, e.g. 128 on Apple M-series
+///and some POWER. `CacheAligned<T>` is aligned like `Padded<T>`
+///
+///```
+///use lockless_datastructures::CacheAligned;
+///use std::sync::atomic::{AtomicUsize, Ordering};
+///
+///struct Stats {
+///    produced: CacheAligned<AtomicUsize, 128>,
+///    consumed: CacheAligned<AtomicUsize, 128>,
+///}
+///
+///let stats = Stats {
+///    produced: CacheAligned::new(AtomicUsize::new(0)),
+///    consumed: CacheAligned::new(AtomicUsize::new(0)),
+///};
+///stats.produced.fetch_add(1, Ordering::Relaxed);
+///assert_eq!(std::mem::align_of::<Stats>(), 128);
+///```
+#[derive(Clone, Copy, Default)]
+pub struct CacheAligned<T, const A: usize = 64>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    _align: [<Alignment<A> as SupportedAlignment>::Aligner; 0],
+    value: T,
+}
+
+impl<T, const A: usize> CacheAligned<T, A>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    pub const fn new(value: T) -> Self {
+        Self { _align: [], value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, const A: usize> Deref for CacheAligned<T, A>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T, const A: usize> DerefMut for CacheAligned<T, A>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<T, const A: usize> From<T> for CacheAligned<T, A>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: fmt::Debug, const A: usize> fmt::Debug for CacheAligned<T, A>
+where
+    Alignment<A>: SupportedAlignment,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CacheAligned").field(&self.value).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Padded;
+    use core::mem::{align_of, size_of};
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(align_of::<CacheAligned<u8>>(), 64);
+        assert_eq!(size_of::<CacheAligned<u8>>(), 64);
+        assert_eq!(align_of::<CacheAligned<u8>>(), align_of::<Padded<u8>>());
+        assert_eq!(align_of::<CacheAligned<u8, 32>>(), 32);
+        assert_eq!(size_of::<CacheAligned<[u8; 40], 32>>(), 64);
+        assert_eq!(align_of::<CacheAligned<u64, 128>>(), 128);
+        assert_eq!(size_of::<CacheAligned<u64, 256>>(), 256);
+
+        //two of them never share a line
+        let pair = [CacheAligned::<u8, 128>::new(1), CacheAligned::new(2)];
+        let (first, second) = (&*pair[0] as *const u8, &*pair[1] as *const u8);
+        assert_eq!(second as usize - first as usize, 128);
+    }
+
+    #[test]
+    fn test_deref() {
+        let mut aligned = CacheAligned::<Vec<u32>, 128>::from(vec![1]);
+        aligned.push(2);
+        assert_eq!(aligned.len(), 2);
+        assert_eq!(format!("{aligned:?}"), "CacheAligned([1, 2])");
+        assert_eq!(aligned.into_inner(), [1, 2]);
+    }
+}
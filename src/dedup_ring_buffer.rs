@@ -0,0 +1,89 @@
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::primitives::Arc;
+use crate::{AtomicRingBufferMpmc, TryPushError};
+
+///An mpmc ring buffer that drops a push if an equal item is still buffered, anywhere in
+///the buffer, not just at the back. Each item is kept twice, once in the ring and a clone
+///in a hash set. Every push and pop takes a lock to keep the two in step, the dedup check
+///itself is a hash lookup so O(1) amortized
+pub struct DedupRingBuffer<T, const N: usize> {
+    ring: Arc<AtomicRingBufferMpmc<T, N>>,
+    buffered: Mutex<HashSet<T>>,
+}
+
+impl<T: Hash + Eq + Clone, const N: usize> DedupRingBuffer<T, N> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ring: AtomicRingBufferMpmc::new(),
+            buffered: Mutex::new(HashSet::with_capacity(N)),
+        })
+    }
+
+    ///Returns `Ok(false)` without pushing if an equal item is already buffered,
+    ///`Ok(true)` once pushed and the value back if the buffer is full
+    pub fn push(&self, value: T) -> Result<bool, TryPushError<T>> {
+        let mut buffered = self.buffered.lock();
+        if buffered.contains(&value) {
+            return Ok(false);
+        }
+        self.ring.push(value.clone())?;
+        buffered.insert(value);
+        Ok(true)
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut buffered = self.buffered.lock();
+        let value = self.ring.pop()?;
+        buffered.remove(&value);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skips_buffered_duplicates() {
+        let buffer = DedupRingBuffer::<&str, 4>::new();
+
+        assert_eq!(buffer.push("A"), Ok(true));
+        assert_eq!(buffer.push("B"), Ok(true));
+        assert_eq!(buffer.push("A"), Ok(false));
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.pop(), Some("A"));
+        assert_eq!(buffer.push("A"), Ok(true));
+        assert_eq!(buffer.push("B"), Ok(false));
+
+        assert_eq!(buffer.pop(), Some("B"));
+        assert_eq!(buffer.pop(), Some("A"));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_full_push_is_not_remembered() {
+        let buffer = DedupRingBuffer::<usize, 2>::new();
+        buffer.push(0).unwrap();
+        buffer.push(1).unwrap();
+        assert_eq!(buffer.push(2), Err(TryPushError::Full(2)));
+
+        buffer.pop();
+        assert_eq!(buffer.push(2), Ok(true));
+    }
+}
@@ -0,0 +1,92 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+///Why `push_batch` gave the items back
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushBatchError<T> {
+    ///Not enough free slots right now, retrying later can succeed
+    Full(Vec<T>),
+    ///The batch is longer than the whole buffer and can never fit
+    TooLarge(Vec<T>),
+}
+
+impl<T> PushBatchError<T> {
+    ///Gives back the items that were not pushed
+    pub fn into_inner(self) -> Vec<T> {
+        match self {
+            Self::Full(items) | Self::TooLarge(items) => items,
+        }
+    }
+}
+
+impl<T> fmt::Display for PushBatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("not enough free space in the buffer for the batch"),
+            Self::TooLarge(items) => {
+                write!(
+                    f,
+                    "batch of {} items is larger than the buffer",
+                    items.len()
+                )
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> core::error::Error for PushBatchError<T> {}
+
+///Why `push` on a mutex buffer gave the value back
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushError<T> {
+    ///No free slot right now, retrying later can succeed
+    Full(T),
+    ///The buffer was closed and takes nothing anymore
+    Closed(T),
+}
+
+impl<T> PushError<T> {
+    ///Gives back the value that was not pushed
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("the buffer is full"),
+            Self::Closed(_) => f.write_str("the buffer is closed"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> core::error::Error for PushError<T> {}
+
+///Why `push` on an atomic buffer gave the value back
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryPushError<T> {
+    ///No free slot right now, retrying later can succeed
+    Full(T),
+}
+
+impl<T> TryPushError<T> {
+    ///Gives back the value that was not pushed
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Display for TryPushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("the buffer is full"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> core::error::Error for TryPushError<T> {}
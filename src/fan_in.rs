@@ -0,0 +1,81 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::AtomicRingBufferSpsc;
+use crate::primitives::Arc;
+
+///Merges several spsc buffers into one consumer, popping from them round robin.
+///The FanIn is the single consumer of every input buffer
+pub struct FanIn<T, const N: usize> {
+    inputs: Vec<Arc<AtomicRingBufferSpsc<T, N>>>,
+    next: AtomicUsize,
+}
+
+impl<T, const N: usize> FanIn<T, N> {
+    pub fn new(inputs: Vec<Arc<AtomicRingBufferSpsc<T, N>>>) -> Self {
+        Self {
+            inputs,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    ///Pops the next item along with the index of the input it came from.
+    ///Each call starts one input after the last one that produced, so a busy input
+    ///can't starve the others
+    pub fn pop(&self) -> Option<(usize, T)> {
+        let len = self.inputs.len();
+        let start = self.next.load(Ordering::Relaxed);
+
+        for offset in 0..len {
+            let idx = (start + offset) % len;
+            if let Some(value) = self.inputs[idx].pop() {
+                self.next.store((idx + 1) % len, Ordering::Relaxed);
+                return Some((idx, value));
+            }
+        }
+
+        None
+    }
+
+    pub fn inputs(&self) -> &[Arc<AtomicRingBufferSpsc<T, N>>] {
+        &self.inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_with_attribution() {
+        let inputs: Vec<_> = (0..3)
+            .map(|_| AtomicRingBufferSpsc::<usize, 8>::new())
+            .collect();
+        for (source, fill) in [1, 3, 5].into_iter().enumerate() {
+            for i in 0..fill {
+                inputs[source].push(source * 100 + i).unwrap();
+            }
+        }
+
+        let fan_in = FanIn::new(inputs);
+        let mut popped = Vec::new();
+        while let Some(item) = fan_in.pop() {
+            popped.push(item);
+        }
+
+        let sources: Vec<usize> = popped.iter().map(|(source, _)| *source).collect();
+        assert_eq!(sources, vec![0, 1, 2, 1, 2, 1, 2, 2, 2]);
+
+        for (source, value) in &popped {
+            assert_eq!(value / 100, *source);
+        }
+        for source in 0..3 {
+            let values: Vec<usize> = popped
+                .iter()
+                .filter(|(s, _)| *s == source)
+                .map(|(_, v)| v % 100)
+                .collect();
+            assert_eq!(values, (0..[1, 3, 5][source]).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::primitives::Arc;
+use crate::{AtomicRingBufferSpsc, TryPushError};
+
+///How a [`FanOut`] picks the output for each item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    ///Take turns, skipping outputs that are full
+    #[default]
+    RoundRobin,
+    ///Send to the output with the fewest buffered items
+    LeastLoaded,
+}
+
+///Spreads items from one producer over several spsc buffers, one per worker.
+///The FanOut is the single producer of every output buffer
+pub struct FanOut<T, const N: usize> {
+    outputs: Vec<Arc<AtomicRingBufferSpsc<T, N>>>,
+    strategy: Strategy,
+    next: AtomicUsize,
+}
+
+impl<T, const N: usize> FanOut<T, N> {
+    pub fn new(outputs: Vec<Arc<AtomicRingBufferSpsc<T, N>>>, strategy: Strategy) -> Self {
+        Self {
+            outputs,
+            strategy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    ///Pushes to the next output picked by the strategy, fails with the value
+    ///only when no output has room for it
+    pub fn push(&self, value: T) -> Result<(), TryPushError<T>> {
+        match self.strategy {
+            Strategy::RoundRobin => self.push_round_robin(value),
+            Strategy::LeastLoaded => self.push_least_loaded(value),
+        }
+    }
+
+    fn push_round_robin(&self, mut value: T) -> Result<(), TryPushError<T>> {
+        let len = self.outputs.len();
+        let start = self.next.load(Ordering::Relaxed);
+
+        for offset in 0..len {
+            let idx = (start + offset) % len;
+            match self.outputs[idx].push(value) {
+                Ok(()) => {
+                    self.next.store((idx + 1) % len, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(back) => value = back.into_inner(),
+            }
+        }
+
+        Err(TryPushError::Full(value))
+    }
+
+    fn push_least_loaded(&self, value: T) -> Result<(), TryPushError<T>> {
+        match self.outputs.iter().min_by_key(|output| output.len()) {
+            Some(output) => output.push(value),
+            None => Err(TryPushError::Full(value)),
+        }
+    }
+
+    pub fn outputs(&self) -> &[Arc<AtomicRingBufferSpsc<T, N>>] {
+        &self.outputs
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_even_distribution() {
+        let outputs: Vec<_> = (0..3)
+            .map(|_| AtomicRingBufferSpsc::<usize, 8>::new())
+            .collect();
+        let fan_out = FanOut::new(outputs, Strategy::RoundRobin);
+
+        for i in 0..12 {
+            fan_out.push(i).unwrap();
+        }
+
+        for (idx, output) in fan_out.outputs().iter().enumerate() {
+            assert_eq!(output.len(), 4);
+            for round in 0..4 {
+                assert_eq!(output.pop(), Some(round * 3 + idx));
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_robin_skips_full_outputs() {
+        let outputs: Vec<_> = (0..2)
+            .map(|_| AtomicRingBufferSpsc::<usize, 2>::new())
+            .collect();
+        outputs[0].push(100).unwrap();
+        outputs[0].push(101).unwrap();
+        let fan_out = FanOut::new(outputs, Strategy::RoundRobin);
+
+        fan_out.push(0).unwrap();
+        fan_out.push(1).unwrap();
+        assert_eq!(fan_out.push(2), Err(TryPushError::Full(2)));
+        assert_eq!(fan_out.outputs()[1].len(), 2);
+    }
+
+    #[test]
+    fn test_least_loaded_placement() {
+        let outputs: Vec<_> = (0..3)
+            .map(|_| AtomicRingBufferSpsc::<usize, 8>::new())
+            .collect();
+        for i in 0..3 {
+            outputs[0].push(i).unwrap();
+        }
+        outputs[2].push(0).unwrap();
+        let fan_out = FanOut::new(outputs, Strategy::LeastLoaded);
+
+        for i in 0..5 {
+            fan_out.push(i).unwrap();
+        }
+        let lens: Vec<usize> = fan_out.outputs().iter().map(|o| o.len()).collect();
+        assert_eq!(lens, vec![3, 3, 3]);
+
+        for i in 0..12 {
+            fan_out.push(i).unwrap();
+        }
+        let lens: Vec<usize> = fan_out.outputs().iter().map(|o| o.len()).collect();
+        assert_eq!(lens, vec![7, 7, 7]);
+    }
+}
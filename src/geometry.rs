@@ -0,0 +1,100 @@
+///Where head and tail are in a buffer, all read together, see `geometry`.
+///Meant for visualizers and other tooling that would otherwise poll several getters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub capacity: usize,
+    ///Head counter as stored, it keeps counting past the capacity
+    pub head_raw: usize,
+    ///Tail counter as stored, it keeps counting past the capacity
+    pub tail_raw: usize,
+    pub len: usize,
+    ///Slot the next push writes to
+    pub head_masked: usize,
+    ///Slot the next pop reads from
+    pub tail_masked: usize,
+    mask: usize,
+}
+
+impl Geometry {
+    ///`mask` is the buffer's `MASK`, capacity minus one, and `head`/`tail` are read
+    ///consistently
+    pub(crate) fn new(mask: usize, head: usize, tail: usize) -> Self {
+        Self {
+            capacity: mask + 1,
+            head_raw: head,
+            tail_raw: tail,
+            len: head.wrapping_sub(tail),
+            head_masked: head & mask,
+            tail_masked: tail & mask,
+            mask,
+        }
+    }
+
+    ///Whether `slot` held an item when the geometry was read. The slot at the tail counts,
+    ///the one at the head doesn't
+    pub fn is_occupied(&self, slot: usize) -> bool {
+        slot.wrapping_sub(self.tail_masked) & self.mask < self.len || self.len == self.capacity
+    }
+}
+
+///What one physical slot of an MPMC buffer holds, see `slot_states`. Shows the
+///sequence numbers that let producers and consumers claim slots without a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotState<T> {
+    ///`position + 1` once the item pushed to `position` is published, `position` while
+    ///the slot waits for the push to `position`
+    pub sequence: usize,
+    pub occupied: bool,
+    ///The item, only for occupied slots
+    pub value: Option<T>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_occupied() {
+        let geometry = Geometry::new(3, 6, 3);
+        assert_eq!(geometry.len, 3);
+        assert_eq!((geometry.head_masked, geometry.tail_masked), (2, 3));
+        let occupied: Vec<bool> = (0..4).map(|i| geometry.is_occupied(i)).collect();
+        assert_eq!(occupied, [true, true, false, true]);
+
+        assert!((0..4).all(|i| Geometry::new(3, 9, 5).is_occupied(i)));
+        assert!((0..4).all(|i| !Geometry::new(3, 5, 5).is_occupied(i)));
+    }
+
+    #[test]
+    fn test_new_wrapping_counters() {
+        let geometry = Geometry::new(3, 1, usize::MAX - 1);
+        assert_eq!(geometry.len, 3);
+        assert_eq!((geometry.head_masked, geometry.tail_masked), (1, 2));
+    }
+
+    ///Each buffer only has to hand its own counters to `Geometry::new`
+    #[test]
+    fn test_buffers_report_their_counters() {
+        macro_rules! check {
+            ($buffer:expr) => {{
+                let buffer = $buffer;
+                for i in 0..7 {
+                    buffer.push(i).ok().unwrap();
+                    if i % 2 == 0 {
+                        buffer.pop().unwrap();
+                    }
+                }
+                assert_eq!(buffer.geometry(), Geometry::new(3, 7, 4));
+                assert_eq!(buffer.geometry().len, buffer.len());
+            }};
+        }
+
+        check!(crate::AtomicRingBufferSpsc::<usize, 4>::new());
+        check!(crate::AtomicRingBufferMpmc::<usize, 4>::new());
+        check!(crate::ShmRingBuffer::<usize, 4>::new());
+        #[cfg(feature = "std")]
+        check!(crate::MutexRingBuffer::<usize, 4>::new());
+        #[cfg(feature = "std")]
+        check!(crate::DynRingBuffer::<usize>::new(4));
+    }
+}
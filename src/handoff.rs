@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::AtomicPtr;
+
+use crate::Backoff;
+use crate::ordering::{ACQ_REL, ACQUIRE, RELEASE};
+use crate::primitives::Arc;
+
+///Hands whole batches of up to N items from a producer to a consumer instead of streaming
+///single items. Double buffered: the producer fills one batch while the consumer works
+///through the other, and a finished batch changes hands with a single pointer swap.
+///The item storage of a batch is recycled once the consumer is done with it
+pub struct Handoff<T, const N: usize> {
+    ready: AtomicPtr<Vec<T>>,
+    spare: AtomicPtr<Vec<T>>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Handoff<T, N> {}
+
+impl<T, const N: usize> Handoff<T, N> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ready: AtomicPtr::new(ptr::null_mut()),
+            spare: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        })
+    }
+
+    ///Lets `f` fill a batch and then hands it to the consumer. If the previous batch
+    ///was not consumed yet this backs off until it is, so batches arrive in order
+    pub fn produce_into(&self, f: impl FnOnce(&FillHandle<T, N>)) {
+        let storage = match self.spare.swap(ptr::null_mut(), ACQUIRE) {
+            storage if storage.is_null() => Vec::with_capacity(N),
+            storage => unsafe { *Box::from_raw(storage) },
+        };
+        let handle = FillHandle {
+            items: RefCell::new(storage),
+        };
+        f(&handle);
+
+        let batch = Box::into_raw(Box::new(handle.items.into_inner()));
+        let mut backoff = Backoff::new();
+        while self
+            .ready
+            .compare_exchange_weak(ptr::null_mut(), batch, RELEASE, ACQUIRE)
+            .is_err()
+        {
+            backoff.snooze();
+        }
+    }
+
+    ///Takes the batch handed off last, if there is one
+    pub fn consume(&self) -> Option<FilledBuffer<'_, T, N>> {
+        let batch = self.ready.swap(ptr::null_mut(), ACQ_REL);
+        if batch.is_null() {
+            return None;
+        }
+        Some(FilledBuffer {
+            handoff: self,
+            items: Some(unsafe { *Box::from_raw(batch) }),
+        })
+    }
+
+    fn recycle(&self, mut storage: Vec<T>) {
+        storage.clear();
+        let storage = Box::into_raw(Box::new(storage));
+        if self
+            .spare
+            .compare_exchange(ptr::null_mut(), storage, RELEASE, ACQUIRE)
+            .is_err()
+        {
+            drop(unsafe { Box::from_raw(storage) });
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for Handoff<T, N> {
+    fn drop(&mut self) {
+        for storage in [self.ready.get_mut(), self.spare.get_mut()] {
+            if !storage.is_null() {
+                drop(unsafe { Box::from_raw(*storage) });
+            }
+        }
+    }
+}
+
+///The batch being filled inside [`Handoff::produce_into`]
+pub struct FillHandle<T, const N: usize> {
+    items: RefCell<Vec<T>>,
+}
+
+impl<T, const N: usize> FillHandle<T, N> {
+    ///Adds to the batch, fails with the value once the batch holds N items
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut items = self.items.borrow_mut();
+        if items.len() == N {
+            return Err(value);
+        }
+        items.push(value);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+///A batch returned by [`Handoff::consume`], its storage goes back to the producer on drop
+pub struct FilledBuffer<'a, T, const N: usize> {
+    handoff: &'a Handoff<T, N>,
+    items: Option<Vec<T>>,
+}
+
+impl<T, const N: usize> FilledBuffer<'_, T, N> {
+    ///Takes the items out, the storage is not recycled then
+    pub fn into_vec(mut self) -> Vec<T> {
+        self.items.take().unwrap_or_default()
+    }
+}
+
+impl<T, const N: usize> Deref for FilledBuffer<'_, T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.items.as_deref().unwrap_or(&[])
+    }
+}
+
+impl<T, const N: usize> Drop for FilledBuffer<'_, T, N> {
+    fn drop(&mut self) {
+        if let Some(items) = self.items.take() {
+            self.handoff.recycle(items);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_arrive_in_order() {
+        let handoff = Handoff::<String, 4>::new();
+        let producer = handoff.clone();
+
+        let handle = std::thread::spawn(move || {
+            for batch in ["A", "B"] {
+                producer.produce_into(|fill| {
+                    for i in 0..5 {
+                        let _ = fill.push(format!("{batch}{i}"));
+                    }
+                    assert_eq!(fill.len(), 4);
+                });
+            }
+        });
+
+        let mut batches = Vec::new();
+        while batches.len() < 2 {
+            match handoff.consume() {
+                Some(filled) => batches.push(filled.to_vec()),
+                None => std::thread::yield_now(),
+            }
+        }
+        handle.join().unwrap();
+
+        assert_eq!(batches[0], ["A0", "A1", "A2", "A3"]);
+        assert_eq!(batches[1], ["B0", "B1", "B2", "B3"]);
+        assert!(handoff.consume().is_none());
+    }
+
+    #[test]
+    fn test_storage_is_recycled() {
+        let handoff = Handoff::<usize, 8>::new();
+        handoff.produce_into(|fill| fill.push(1).unwrap());
+        let first = handoff.consume().unwrap();
+        let storage = first.as_ptr();
+        drop(first);
+
+        handoff.produce_into(|fill| fill.push(2).unwrap());
+        let second = handoff.consume().unwrap();
+        assert_eq!(second.as_ptr(), storage);
+        assert_eq!(second.into_vec(), [2]);
+    }
+}
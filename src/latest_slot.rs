@@ -0,0 +1,181 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicUsize;
+
+use crate::Padded;
+use crate::ordering::{ACQ_REL, RELAXED};
+use crate::primitives::Arc;
+
+///Set in `middle` while the slot it names holds a value the consumer has not taken yet
+const FRESH: usize = 0b100;
+///Masks the slot index out of `middle`
+const INDEX: usize = 0b011;
+
+///Latest value wins slot for one producer and one consumer, like a watch channel. A
+///triple buffer: the producer writes into its own slot and swaps it with the shared
+///middle one, the consumer swaps the middle one with its own slot to read. Neither
+///ever touches a slot the other one holds, so any `T` works and nothing allocates
+///after `new`.
+///
+///If the consumer has not taken the previous value yet, `push_latest` hands it back,
+///so the consumer only ever sees the newest value
+///
+///```
+///use lockless_datastructures::LatestSlot;
+///
+///let slot = LatestSlot::new();
+///assert_eq!(slot.push_latest(1), None);
+///assert_eq!(slot.push_latest(2), Some(1));
+///assert_eq!(slot.pop(), Some(2));
+///assert_eq!(slot.pop(), None);
+///```
+pub struct LatestSlot<T> {
+    slots: [UnsafeCell<MaybeUninit<T>>; 3],
+    ///Index of the slot between the two ends, with `FRESH` set while it holds a value
+    middle: Padded<AtomicUsize>,
+    ///Slot the producer writes into next, always empty between pushes
+    back: UnsafeCell<usize>,
+    ///Slot the consumer last read from, always empty between pops
+    front: UnsafeCell<usize>,
+}
+unsafe impl<T: Send> Send for LatestSlot<T> {}
+unsafe impl<T: Send> Sync for LatestSlot<T> {}
+
+impl<T> LatestSlot<T> {
+    ///Identifies the synchronization used, handy for labelling metrics
+    pub const KIND: &str = "latest-slot";
+
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; 3],
+            middle: Padded(AtomicUsize::new(1)),
+            back: UnsafeCell::new(0),
+            front: UnsafeCell::new(2),
+        })
+    }
+
+    pub fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    ///Publishes `value`, returning the previous value if the consumer has not taken it
+    ///yet. Never blocks or fails. Only the producer should call this
+    pub fn push_latest(&self, value: T) -> Option<T> {
+        unsafe {
+            let back = self.back.get().read();
+            (*self.slots[back].get()).write(value);
+
+            let previous = self.middle.swap(back | FRESH, ACQ_REL);
+            let back = previous & INDEX;
+            self.back.get().write(back);
+            //the consumer never saw what was left in the middle, it is ours again
+            (previous & FRESH != 0).then(|| (*self.slots[back].get()).assume_init_read())
+        }
+    }
+
+    ///Takes the newest value, None if there is nothing new since the last pop.
+    ///Only the consumer should call this
+    pub fn pop(&self) -> Option<T> {
+        if self.middle.load(RELAXED) & FRESH == 0 {
+            return None;
+        }
+        unsafe {
+            //only this side clears FRESH, so the middle slot still holds a value
+            let previous = self.middle.swap(self.front.get().read(), ACQ_REL);
+            let front = previous & INDEX;
+            self.front.get().write(front);
+            Some((*self.slots[front].get()).assume_init_read())
+        }
+    }
+
+    ///Whether a value is waiting for the consumer
+    pub fn has_value(&self) -> bool {
+        self.middle.load(RELAXED) & FRESH != 0
+    }
+}
+
+impl<T> Drop for LatestSlot<T> {
+    fn drop(&mut self) {
+        let middle = *self.middle.get_mut();
+        if middle & FRESH != 0 {
+            unsafe { self.slots[middle & INDEX].get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_push_latest() {
+        let slot = LatestSlot::<String>::new();
+
+        assert_eq!(slot.push_latest("a".to_string()), None);
+        assert_eq!(slot.push_latest("b".to_string()).as_deref(), Some("a"));
+        assert_eq!(slot.push_latest("c".to_string()).as_deref(), Some("b"));
+        assert!(slot.has_value());
+        assert_eq!(slot.pop().as_deref(), Some("c"));
+        assert_eq!(slot.pop(), None);
+        assert!(!slot.has_value());
+
+        assert_eq!(slot.push_latest("d".to_string()), None);
+        assert_eq!(slot.pop().as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn test_push_latest_threaded() {
+        const COUNT: usize = 10_000;
+
+        let slot = LatestSlot::<Box<usize>>::new();
+        let consumer_slot = slot.clone();
+        let done = std::sync::Arc::new(AtomicBool::new(false));
+        let consumer_done = done.clone();
+
+        let consumer = thread::spawn(move || {
+            let mut seen = Vec::new();
+            loop {
+                match consumer_slot.pop() {
+                    Some(value) => seen.push(*value),
+                    None if consumer_done.load(Ordering::Acquire) => break,
+                    None => thread::yield_now(),
+                }
+            }
+            while let Some(value) = consumer_slot.pop() {
+                seen.push(*value);
+            }
+            seen
+        });
+
+        let mut superseded = Vec::new();
+        for i in 0..COUNT {
+            if let Some(old) = slot.push_latest(Box::new(i)) {
+                superseded.push(*old);
+            }
+        }
+        done.store(true, Ordering::Release);
+        let seen = consumer.join().unwrap();
+
+        //the consumer only moves forward and ends on the newest value
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(seen.last(), Some(&(COUNT - 1)));
+        //every value is either seen or handed back, exactly once
+        let mut all: Vec<usize> = seen.into_iter().chain(superseded).collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..COUNT).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drops_unread_value() {
+        let value = std::sync::Arc::new(());
+        let slot = LatestSlot::new();
+        slot.push_latest(value.clone());
+        //the first one is handed back and dropped right away
+        drop(slot.push_latest(value.clone()));
+        assert_eq!(std::sync::Arc::strong_count(&value), 2);
+        drop(slot);
+        assert_eq!(std::sync::Arc::strong_count(&value), 1);
+    }
+}
@@ -1,16 +1,136 @@
-use std::ops::{Deref, DerefMut};
+//!A bunch of lockless datastructures
+//!
+//!The buffers are generic over any `T`, references included. A buffer holding `&'a U`
+//!is itself bound to `'a`, so the borrow checker stops it from outliving what it points to:
+//!
+//!```compile_fail
+//!use lockless_datastructures::AtomicRingBufferSpsc;
+//!
+//!let buffer = AtomicRingBufferSpsc::<&String, 4>::new();
+//!{
+//!    let short_lived = String::from("gone soon");
+//!    buffer.push(&short_lived).unwrap();
+//!}
+//!buffer.pop();
+//!```
+//!
+//!Sharing a buffer with another thread needs `T: 'static` anyway (`std::thread::spawn`
+//!requires it), so in practice long lived pipelines should store owned values.
+//!
+//!Without the default `std` feature the crate is `no_std` (it still needs `alloc`). The
+//!atomic buffers, [`Arc`] and [`Backoff`] stay available, everything built on mutexes,
+//!condvars, clocks or the browser is left out.
+//!
+//!The crate is also built as a `cdylib` for the WASM demo, and that can't link without
+//!`std`, so a plain `cargo build --no-default-features` fails. Build only the rlib instead:
+//!
+//!```text
+//!cargo rustc --lib --no-default-features --crate-type rlib
+//!```
+//!
+//!The `hybrid` feature gives [`AtomicRingBufferMpmc`] a slow path: an operation that keeps
+//!losing its CAS takes a lock, and every other push and pop waits until it is done. That
+//!bounds how long one operation can starve, but the buffer is no longer lock free, a
+//!preempted lock holder stalls everyone.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::ops::{Deref, DerefMut};
+
+///Runs the statement only when the `metrics` feature is on
+macro_rules! with_metrics {
+    ($($body:tt)*) => {
+        #[cfg(feature = "metrics")]
+        {
+            $($body)*
+        }
+    };
+}
+
+///Emits a trace level event when the `tracing` feature is on, compiles to nothing otherwise
+macro_rules! trace_op {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
 
 mod atomic_ring_buffer_mpmc;
 mod atomic_ring_buffer_spsc;
+mod backoff;
+#[cfg(feature = "std")]
+mod broadcast_ring_buffer;
+#[cfg(all(test, feature = "std"))]
+mod buffer_tests;
+mod cache_aligned;
+#[cfg(feature = "std")]
+mod dedup_ring_buffer;
+mod error;
+mod fan_in;
+mod fan_out;
+mod geometry;
+#[cfg(feature = "std")]
+mod handoff;
+mod latest_slot;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod ms_queue;
+#[cfg(feature = "std")]
 mod mutex_ring_buffer;
+#[cfg(feature = "record")]
+mod op_log;
+mod ordering;
+mod pop_buffer;
 mod primitives;
+mod reclaim;
+#[cfg(feature = "std")]
 mod render;
+mod shm_ring_buffer;
+#[cfg(feature = "std")]
+mod spillover_queue;
+#[cfg(all(test, feature = "test-alloc"))]
+mod test_alloc;
+mod treiber_stack;
+#[cfg(feature = "async")]
+mod wakers;
 
 pub use self::atomic_ring_buffer_mpmc::AtomicRingBufferMpmc;
-pub use self::atomic_ring_buffer_spsc::AtomicRingBufferSpsc;
-pub use self::mutex_ring_buffer::MutexRingBuffer;
+#[cfg(feature = "debug-checks")]
+pub use self::atomic_ring_buffer_mpmc::StallReport;
+pub use self::atomic_ring_buffer_spsc::{
+    AtomicRingBufferSpsc, Chunks, Consumer, Producer, PushOutcome, ReadTransaction, Ticket,
+};
+pub use self::backoff::Backoff;
+#[cfg(feature = "std")]
+pub use self::broadcast_ring_buffer::{BroadcastRingBuffer, Publisher, Subscriber};
+pub use self::cache_aligned::{Alignment, CacheAligned, SupportedAlignment};
+#[cfg(feature = "std")]
+pub use self::dedup_ring_buffer::DedupRingBuffer;
+pub use self::error::{PushBatchError, PushError, TryPushError};
+pub use self::fan_in::FanIn;
+pub use self::fan_out::{FanOut, Strategy};
+pub use self::geometry::{Geometry, SlotState};
+#[cfg(feature = "std")]
+pub use self::handoff::{FillHandle, FilledBuffer, Handoff};
+pub use self::latest_slot::LatestSlot;
+#[cfg(feature = "metrics")]
+pub use self::metrics::{ContentionLevel, Metrics};
+pub use self::ms_queue::MsQueue;
+#[cfg(feature = "std")]
+pub use self::mutex_ring_buffer::{Drain, DynRingBuffer, Fifo, Lifo, MutexRingBuffer, Order};
+#[cfg(feature = "record")]
+pub use self::op_log::{Op, OpRecord};
+pub use self::pop_buffer::PopBuffer;
+pub use self::primitives::{Arc, Weak};
+pub use self::shm_ring_buffer::ShmRingBuffer;
+#[cfg(feature = "std")]
+pub use self::spillover_queue::SpilloverQueue;
+pub use self::treiber_stack::TreiberStack;
 
-///Use to prevent cache line collision!
+///Use to prevent cache line collision! Always 64 bytes, see [`CacheAligned`] for
+///other line sizes
 #[derive(Debug, Default)]
 #[repr(align(64))]
 pub struct Padded<T>(pub T);
@@ -27,39 +147,30 @@ impl<T> DerefMut for Padded<T> {
     }
 }
 
-use std::hint;
-use std::thread;
-///An exponential backoff
-pub struct Backoff {
-    step: u32,
-}
-impl Default for Backoff {
-    fn default() -> Self {
-        Self::new()
+///The body of the buffers' `fill_to`: pushes default items until `level` are buffered,
+///stopping early if a push fails
+pub(crate) fn fill_with_default<T: Default, E>(
+    capacity: usize,
+    level: usize,
+    len: usize,
+    mut push: impl FnMut(T) -> Result<(), E>,
+) {
+    assert!(
+        level <= capacity,
+        "Fill level {level} is above the capacity {capacity}"
+    );
+    for _ in len..level {
+        if push(T::default()).is_err() {
+            break;
+        }
     }
 }
-impl Backoff {
-    pub fn new() -> Self {
-        Self { step: 0 }
-    }
-    ///Call this where you want to backoff!
-    #[inline]
-    pub fn snooze(&mut self) {
-        if self.step <= 6 {
-            for _ in 0..(1 << self.step) {
-                hint::spin_loop();
-            }
-        } else {
-            thread::yield_now();
-        }
 
-        if self.step <= 6 {
-            self.step += 1;
-        }
-    }
+///A callback run on every element still buffered when a buffer is dropped
+pub(crate) struct DropObserver<T>(Box<dyn Fn(&T) + Send + Sync>);
 
-    #[inline]
-    pub fn reset(&mut self) {
-        self.step = 0;
+impl<T> core::fmt::Debug for DropObserver<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("DropObserver")
     }
 }
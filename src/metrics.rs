@@ -0,0 +1,203 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+///Fixed point scale of the occupancy average, 1.0 is a full buffer
+const OCCUPANCY_ONE: usize = 1 << 16;
+///The average decays by 1/OCCUPANCY_WINDOW per sample, so roughly the last
+///OCCUPANCY_WINDOW operations matter
+const OCCUPANCY_WINDOW: usize = 64;
+///Above this share of the capacity on average the buffer counts as chronically full
+const CHRONICALLY_FULL: usize = OCCUPANCY_ONE * 9 / 10;
+///Fixed point scale of the CAS retry average, 1.0 is one retry per operation
+const RETRY_ONE: usize = 1 << 16;
+///Samples are capped here so a single pathological operation can't overflow the average
+const RETRY_SAMPLE_CAP: usize = 1024;
+///Average retries per operation from which contention counts as medium and high
+const MEDIUM_CONTENTION: usize = RETRY_ONE / 10;
+const HIGH_CONTENTION: usize = RETRY_ONE;
+
+///How hard operations fight over the buffer, see `contention_level`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContentionLevel {
+    ///Fewer than one retry per ten operations
+    Low,
+    ///Some operations retry but most get through on the first CAS
+    Medium,
+    ///At least one retry per operation on average, batching would take pressure off
+    High,
+}
+
+///All the counters of a buffer read together, see `metrics_snapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Metrics {
+    ///Pops that found the buffer empty
+    pub empty_polls: usize,
+    ///Pops that got an item
+    pub hit_polls: usize,
+    ///Most CAS retries a single operation needed, always 0 for buffers without CAS loops
+    pub max_cas_retries: usize,
+    ///Highest lag seen by a push, always 0 for buffers that don't track lag
+    pub max_lag: usize,
+    ///Moving average of occupancy as a share of the capacity, 1.0 is a full buffer
+    pub occupancy_average: f64,
+    ///Moving average of CAS retries per operation
+    pub retry_average: f64,
+}
+
+///Counters the buffers keep when the `metrics` feature is on
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    empty_polls: AtomicUsize,
+    hit_polls: AtomicUsize,
+    max_cas_retries: AtomicUsize,
+    ///Highest lag seen by a push, only the atomic buffers track it since it costs every
+    ///push a load of tail
+    max_lag: AtomicUsize,
+    ///Exponentially decaying average of occupancy / capacity, scaled by OCCUPANCY_ONE
+    occupancy_average: AtomicUsize,
+    ///Exponentially decaying average of CAS retries per operation, scaled by RETRY_ONE
+    retry_average: AtomicUsize,
+}
+
+impl Counters {
+    ///Folds one occupancy sample into the average. A plain load and store, racing
+    ///updates can drop a sample which is fine for a trend
+    #[inline]
+    pub(crate) fn record_occupancy(&self, len: usize, capacity: usize) {
+        let sample = len * OCCUPANCY_ONE / capacity;
+        let average = self.occupancy_average.load(Ordering::Relaxed);
+        let average = average - average / OCCUPANCY_WINDOW + sample / OCCUPANCY_WINDOW;
+        self.occupancy_average.store(average, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_chronically_full(&self) -> bool {
+        self.occupancy_average.load(Ordering::Relaxed) > CHRONICALLY_FULL
+    }
+
+    #[inline]
+    pub(crate) fn record_poll(&self, hit: bool) {
+        if hit {
+            self.hit_polls.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.empty_polls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    ///Only touches the max counter when a new maximum shows up. The average is folded the
+    ///same racy way as the occupancy one
+    #[inline]
+    pub(crate) fn record_cas_retries(&self, retries: usize) {
+        if retries > self.max_cas_retries.load(Ordering::Relaxed) {
+            self.max_cas_retries.fetch_max(retries, Ordering::Relaxed);
+        }
+
+        let sample = retries.min(RETRY_SAMPLE_CAP) * RETRY_ONE;
+        let average = self.retry_average.load(Ordering::Relaxed);
+        let average = average - average / OCCUPANCY_WINDOW + sample / OCCUPANCY_WINDOW;
+        self.retry_average.store(average, Ordering::Relaxed);
+    }
+
+    pub(crate) fn contention_level(&self) -> ContentionLevel {
+        match self.retry_average.load(Ordering::Relaxed) {
+            average if average >= HIGH_CONTENTION => ContentionLevel::High,
+            average if average >= MEDIUM_CONTENTION => ContentionLevel::Medium,
+            _ => ContentionLevel::Low,
+        }
+    }
+
+    pub(crate) fn max_cas_retries(&self) -> usize {
+        self.max_cas_retries.load(Ordering::Relaxed)
+    }
+
+    ///Lags above the capacity come from a racing pop and are ignored
+    #[inline]
+    pub(crate) fn record_lag(&self, lag: usize, capacity: usize) {
+        if lag <= capacity && lag > self.max_lag.load(Ordering::Relaxed) {
+            self.max_lag.fetch_max(lag, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn max_lag(&self) -> usize {
+        self.max_lag.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset_lag(&self) {
+        self.max_lag.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            empty_polls: self.empty_polls.load(Ordering::Relaxed),
+            hit_polls: self.hit_polls.load(Ordering::Relaxed),
+            max_cas_retries: self.max_cas_retries.load(Ordering::Relaxed),
+            max_lag: self.max_lag.load(Ordering::Relaxed),
+            occupancy_average: Self::occupancy_share(
+                self.occupancy_average.load(Ordering::Relaxed),
+            ),
+            retry_average: Self::retries_per_op(self.retry_average.load(Ordering::Relaxed)),
+        }
+    }
+
+    ///Zeroes every counter and returns what they held, so nothing counted in between
+    ///a snapshot and a reset gets lost
+    pub(crate) fn reset(&self) -> Metrics {
+        Metrics {
+            empty_polls: self.empty_polls.swap(0, Ordering::Relaxed),
+            hit_polls: self.hit_polls.swap(0, Ordering::Relaxed),
+            max_cas_retries: self.max_cas_retries.swap(0, Ordering::Relaxed),
+            max_lag: self.max_lag.swap(0, Ordering::Relaxed),
+            occupancy_average: Self::occupancy_share(
+                self.occupancy_average.swap(0, Ordering::Relaxed),
+            ),
+            retry_average: Self::retries_per_op(self.retry_average.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    fn occupancy_share(average: usize) -> f64 {
+        average as f64 / OCCUPANCY_ONE as f64
+    }
+
+    fn retries_per_op(average: usize) -> f64 {
+        average as f64 / RETRY_ONE as f64
+    }
+
+    pub(crate) fn empty_poll_ratio(&self) -> f64 {
+        let empty = self.empty_polls.load(Ordering::Relaxed);
+        let total = empty + self.hit_polls.load(Ordering::Relaxed);
+        if total == 0 {
+            0.0
+        } else {
+            empty as f64 / total as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///The classification alone, fed the retry counts a contended buffer would see. Racing
+    ///threads can't produce those reliably on a machine with few cores
+    #[test]
+    fn test_contention_level_thresholds() {
+        let counters = Counters::default();
+        assert_eq!(counters.contention_level(), ContentionLevel::Low);
+
+        for _ in 0..200 {
+            counters.record_cas_retries(3);
+        }
+        assert_eq!(counters.contention_level(), ContentionLevel::High);
+
+        //a few retries here and there
+        for i in 0..400 {
+            counters.record_cas_retries(usize::from(i % 3 == 0));
+        }
+        assert_eq!(counters.contention_level(), ContentionLevel::Medium);
+
+        //calm again once uncontended operations take over
+        for _ in 0..1000 {
+            counters.record_cas_retries(0);
+        }
+        assert_eq!(counters.contention_level(), ContentionLevel::Low);
+        assert_eq!(counters.max_cas_retries(), 3);
+    }
+}
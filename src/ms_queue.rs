@@ -0,0 +1,280 @@
+use alloc::boxed::Box;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+
+use crate::reclaim::Reclaimer;
+use crate::{Backoff, Padded, primitives::Arc};
+
+struct Node<T> {
+    ///Uninit in the dummy node head points to, taken out by the dequeue that makes
+    ///this node the new dummy
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn boxed(value: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+///Unbounded lock free FIFO, the Michael-Scott queue. A linked list that always starts
+///with a dummy node: head points to the dummy and the first value sits in the node after
+///it, tail points to the last node or lags one behind it. Enqueues link their node after
+///the last one with a CAS and then swing tail, dequeues CAS head one node forward and the
+///node they land on becomes the new dummy. Whoever sees tail lagging swings it first, so
+///no operation waits on another one that got preempted.
+///
+///The old dummy is unlinked, but other operations may still be reading it, so every
+///node is protected with a hazard pointer before it is read, the same way as the nodes
+///of [`TreiberStack`](crate::TreiberStack)
+///
+///```
+///use lockless_datastructures::MsQueue;
+///
+///let queue = MsQueue::new();
+///queue.enqueue(1);
+///queue.enqueue(2);
+///assert_eq!(queue.dequeue(), Some(1));
+///assert_eq!(queue.dequeue(), Some(2));
+///assert_eq!(queue.dequeue(), None);
+///```
+pub struct MsQueue<T> {
+    head: Padded<AtomicPtr<Node<T>>>,
+    tail: Padded<AtomicPtr<Node<T>>>,
+    reclaimer: Reclaimer<Node<T>>,
+}
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T> MsQueue<T> {
+    ///Identifies the synchronization used, handy for labelling metrics
+    pub const KIND: &str = "ms-queue";
+
+    pub fn new() -> Arc<Self> {
+        let dummy = Node::boxed(MaybeUninit::uninit());
+        Arc::new(Self {
+            head: Padded(AtomicPtr::new(dummy)),
+            tail: Padded(AtomicPtr::new(dummy)),
+            reclaimer: Reclaimer::new(),
+        })
+    }
+
+    pub fn enqueue(&self, value: T) {
+        let node = Node::boxed(MaybeUninit::new(value));
+        let guard = self.reclaimer.enter();
+
+        let mut backoff = Backoff::new();
+        loop {
+            let tail = guard.protect_load(0, &self.tail);
+            let next = unsafe { (*tail).next.load(SeqCst) };
+            if tail == self.tail.load(SeqCst) {
+                if next.is_null() {
+                    let linked = unsafe {
+                        (*tail)
+                            .next
+                            .compare_exchange(next, node, SeqCst, SeqCst)
+                            .is_ok()
+                    };
+                    if linked {
+                        //fine if this fails, someone already swung tail for us
+                        let _ = self.tail.compare_exchange(tail, node, SeqCst, SeqCst);
+                        break;
+                    }
+                } else {
+                    let _ = self.tail.compare_exchange(tail, next, SeqCst, SeqCst);
+                }
+            }
+            backoff.snooze();
+        }
+
+        unsafe { self.reclaimer.leave(guard, None) };
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        let guard = self.reclaimer.enter();
+
+        let mut backoff = Backoff::new();
+        loop {
+            let head = guard.protect_load(0, &self.head);
+            let tail = self.tail.load(SeqCst);
+            let next = unsafe { (*head).next.load(SeqCst) };
+            guard.protect(1, next);
+            //head still in place means next is still linked, so the hazard went up in time
+            if head == self.head.load(SeqCst) {
+                if head == tail {
+                    if next.is_null() {
+                        unsafe { self.reclaimer.leave(guard, None) };
+                        return None;
+                    }
+                    //tail lags behind, swing it before head can pass it
+                    let _ = self.tail.compare_exchange(tail, next, SeqCst, SeqCst);
+                } else if self
+                    .head
+                    .compare_exchange(head, next, SeqCst, SeqCst)
+                    .is_ok()
+                {
+                    //next is the new dummy, only the winner takes its value
+                    let value = unsafe { (*next).value.assume_init_read() };
+                    unsafe { self.reclaimer.leave(guard, Some(head)) };
+                    return Some(value);
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    ///A snapshot, other threads may enqueue or dequeue right after
+    pub fn is_empty(&self) -> bool {
+        let guard = self.reclaimer.enter();
+        let head = guard.protect_load(0, &self.head);
+        let empty = unsafe { (*head).next.load(SeqCst).is_null() };
+        unsafe { self.reclaimer.leave(guard, None) };
+        empty
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut node = dummy.next.load(SeqCst);
+        while !node.is_null() {
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { boxed.value.assume_init_drop() };
+            node = boxed.next.load(SeqCst);
+        }
+        //old dummies still waiting on the reclaimer are freed by its own drop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_fifo() {
+        let queue = MsQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.dequeue(), None);
+
+        for i in 0..10 {
+            queue.enqueue(i);
+        }
+        assert!(!queue.is_empty());
+        for i in 0..5 {
+            assert_eq!(queue.dequeue(), Some(i));
+        }
+        queue.enqueue(42);
+        for i in 5..10 {
+            assert_eq!(queue.dequeue(), Some(i));
+        }
+        assert_eq!(queue.dequeue(), Some(42));
+        assert_eq!(queue.dequeue(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_mpmc_concurrency() {
+        const NUM_PRODUCERS: usize = 4;
+        const NUM_CONSUMERS: usize = 4;
+        #[cfg(not(miri))]
+        const OPS_PER_THREAD: usize = 10_000;
+        #[cfg(miri)]
+        const OPS_PER_THREAD: usize = 100;
+
+        let queue: Arc<MsQueue<usize>> = MsQueue::new();
+        let barrier = Arc::new(Barrier::new(NUM_PRODUCERS + NUM_CONSUMERS));
+
+        let mut handles = vec![];
+
+        for p_id in 0..NUM_PRODUCERS {
+            let q = queue.clone();
+            let b = barrier.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+                for i in 0..OPS_PER_THREAD {
+                    q.enqueue(p_id * OPS_PER_THREAD + i);
+                }
+            }));
+        }
+
+        let results = Arc::new(AtomicUsize::new(0));
+        let sum = Arc::new(AtomicUsize::new(0));
+        for _ in 0..NUM_CONSUMERS {
+            let q = queue.clone();
+            let b = barrier.clone();
+            let r = results.clone();
+            let total = sum.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+
+                //each producer's values come out in the order it enqueued them
+                let mut last = [None; NUM_PRODUCERS];
+                loop {
+                    match q.dequeue() {
+                        Some(value) => {
+                            let producer = value / OPS_PER_THREAD;
+                            assert!(last[producer] < Some(value));
+                            last[producer] = Some(value);
+                            total.fetch_add(value, Ordering::Relaxed);
+                            r.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => {
+                            if r.load(Ordering::Relaxed) == NUM_PRODUCERS * OPS_PER_THREAD {
+                                break;
+                            }
+                            std::thread::yield_now();
+                        }
+                    }
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let total = NUM_PRODUCERS * OPS_PER_THREAD;
+        assert_eq!(results.load(Ordering::SeqCst), total);
+        assert_eq!(sum.load(Ordering::SeqCst), total * (total - 1) / 2);
+        assert!(queue.is_empty());
+    }
+
+    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct DropTracker;
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_drop_cleanup() {
+        DROP_COUNTER.store(0, Ordering::Relaxed);
+
+        {
+            let queue = MsQueue::new();
+
+            for _ in 0..5 {
+                queue.enqueue(DropTracker);
+            }
+
+            queue.dequeue();
+            queue.dequeue();
+
+            assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 2);
+        }
+
+        assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 5);
+    }
+}
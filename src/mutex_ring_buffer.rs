@@ -1,26 +1,77 @@
-use parking_lot::Mutex;
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use crate::primitives::Arc;
+use crate::{Backoff, DropObserver, Geometry, PushError};
 
 #[derive(Debug)]
 struct RingBuffer<T, const N: usize> {
     head: usize,
     tail: usize,
     buffer: [MaybeUninit<T>; N],
+    ///How far head has moved back in total, wrapping: by `Lifo` pops, by compaction and
+    ///by `remove`. A `Drain` pulls the end it snapshotted back by as much
+    retreats: usize,
+    closed: bool,
+    drop_observer: Option<DropObserver<T>>,
+}
+
+#[derive(Debug)]
+struct Shared<T, const N: usize> {
+    ring_buffer: Mutex<RingBuffer<T, N>>,
+    ///Signalled whenever an item is pushed or the buffer is closed
+    pushed: Condvar,
+    ///Signalled whenever a slot frees up or the buffer is closed
+    popped: Condvar,
+    ///Whether blocking ops spin for a while before parking, see `set_adaptive_spin`
+    adaptive_spin: AtomicBool,
+    #[cfg(feature = "metrics")]
+    metrics: crate::metrics::Counters,
+}
+
+///Picks which end of a [`MutexRingBuffer`] `pop` takes from
+pub trait Order {
+    const LIFO: bool;
+}
+
+///Pop the oldest item, a queue (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fifo;
+
+///Pop the most recently pushed item, a stack
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lifo;
+
+impl Order for Fifo {
+    const LIFO: bool = false;
+}
+
+impl Order for Lifo {
+    const LIFO: bool = true;
 }
 
 ///A mutex protected RingBuffer
 #[derive(Debug, Clone)]
-pub struct MutexRingBuffer<T, const N: usize>(Arc<Mutex<RingBuffer<T, N>>>);
+pub struct MutexRingBuffer<T, const N: usize, O: Order = Fifo>(
+    Arc<Shared<T, N>>,
+    PhantomData<fn() -> O>,
+);
 
-impl<T, const N: usize> Default for MutexRingBuffer<T, N> {
+impl<T, const N: usize, O: Order> Default for MutexRingBuffer<T, N, O> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<T, const N: usize> MutexRingBuffer<T, N> {
+impl<T, const N: usize, O: Order> MutexRingBuffer<T, N, O> {
+    ///Identifies the synchronization used, the mutex buffer serves both spsc and mpmc use
+    pub const KIND: &str = "mutex-spsc/mpmc";
+
     pub fn new() -> Self {
         const {
             assert!(
@@ -28,57 +79,674 @@ impl<T, const N: usize> MutexRingBuffer<T, N> {
                 "Buffer size N must be a power of two"
             )
         };
-        Self(Arc::new(Mutex::new(RingBuffer {
-            buffer: std::array::from_fn(|_| MaybeUninit::uninit()),
-            head: 0,
-            tail: 0,
-        })))
+        Self(
+            Arc::new(Shared {
+                ring_buffer: Mutex::new(RingBuffer {
+                    buffer: std::array::from_fn(|_| MaybeUninit::uninit()),
+                    head: 0,
+                    tail: 0,
+                    retreats: 0,
+                    closed: false,
+                    drop_observer: None,
+                }),
+                pushed: Condvar::new(),
+                popped: Condvar::new(),
+                adaptive_spin: AtomicBool::new(false),
+                #[cfg(feature = "metrics")]
+                metrics: Default::default(),
+            }),
+            PhantomData,
+        )
     }
 
-    pub fn push(&self, value: T) -> Result<(), T> {
-        let mut ring_buffer = self.0.lock();
+    ///Moves the items of `items` in, front to back, so `pop` on a `Fifo` buffer returns
+    ///them in the same order. Gives `items` back untouched if there are more than N
+    pub fn from_vec_deque(items: VecDeque<T>) -> Result<Self, VecDeque<T>> {
+        if items.len() > N {
+            return Err(items);
+        }
+        let buffer = Self::new();
+        {
+            let mut ring_buffer = buffer.lock();
+            for value in items {
+                ring_buffer.push_back(value);
+            }
+        }
+        Ok(buffer)
+    }
 
+    ///Registers a callback that sees every element still buffered when the buffer is
+    ///dropped, in FIFO order and right before each one is destroyed. Handy for logging
+    ///messages lost at shutdown. Only the first observer sticks, later calls drop `f`
+    ///and return false
+    pub fn set_drop_observer(&self, f: impl Fn(&T) + Send + Sync + 'static) -> bool {
+        let mut ring_buffer = self.lock();
+        if ring_buffer.drop_observer.is_some() {
+            return false;
+        }
+        ring_buffer.drop_observer = Some(DropObserver(Box::new(f)));
+        true
+    }
+
+    ///Makes blocking ops like `pop_when_filled` poll with a short backoff before parking
+    ///on the condvar, off by default. Worth it when the wait is usually only a few
+    ///microseconds, e.g. a consumer that keeps up with a fast producer, since parking and
+    ///waking a thread costs more than that. Leave it off when waits are long, spinning
+    ///then just burns CPU before parking anyway. Only timing changes, never results
+    pub fn set_adaptive_spin(&self, spin: bool) {
+        self.0.adaptive_spin.store(spin, Ordering::Relaxed);
+    }
+
+    pub fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    ///Fails with the value if the buffer is full or closed, the variant tells which
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        let ring_buffer = self.lock();
+
+        if ring_buffer.closed {
+            return Err(PushError::Closed(value));
+        }
         if ring_buffer.head.wrapping_sub(ring_buffer.tail) == N {
-            return Err(value);
+            trace_op!(
+                kind = Self::KIND,
+                occupancy = N,
+                "push rejected, buffer full"
+            );
+            return Err(PushError::Full(value));
         }
 
-        let idx = Self::mask(ring_buffer.head);
-        unsafe {
-            ring_buffer.buffer.get_unchecked_mut(idx).write(value);
+        self.put(ring_buffer, value);
+        Ok(())
+    }
+
+    ///Waits for a free slot instead of failing on a full buffer, so a slow consumer
+    ///throttles its producers. Only fails, with `PushError::Closed`, if the buffer is
+    ///closed before the value got in
+    pub fn push_blocking(&self, value: T) -> Result<(), PushError<T>> {
+        self.push_waiting(value, None)
+    }
+
+    ///Like `push_blocking` but gives up once `timeout` has passed, failing with
+    ///`PushError::Full`. Spurious wakeups only wait out what is left of the timeout
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), PushError<T>> {
+        self.push_waiting(value, Instant::now().checked_add(timeout))
+    }
+
+    fn push_waiting(&self, value: T, deadline: Option<Instant>) -> Result<(), PushError<T>> {
+        let mut ring_buffer = self.lock();
+        self.wait_while(&self.0.popped, &mut ring_buffer, deadline, |ring_buffer| {
+            ring_buffer.len() == N && !ring_buffer.closed
+        });
+        if ring_buffer.closed {
+            return Err(PushError::Closed(value));
+        }
+        if ring_buffer.len() == N {
+            return Err(PushError::Full(value));
         }
-        ring_buffer.head = ring_buffer.head.wrapping_add(1);
+
+        self.put(ring_buffer, value);
         Ok(())
     }
 
+    ///Never fails for lack of space: on a full buffer the oldest item is evicted to make
+    ///room and returned as `Ok(Some(oldest))`, so callers can count what they lost. The
+    ///oldest item is at the tail for `Lifo` buffers too. A closed buffer takes nothing
+    ///and hands `value` back in `PushError::Closed`, the only error this returns
+    pub fn push_overwrite(&self, value: T) -> Result<Option<T>, PushError<T>> {
+        let mut ring_buffer = self.lock();
+        if ring_buffer.closed {
+            return Err(PushError::Closed(value));
+        }
+
+        let evicted = if ring_buffer.len() == N {
+            let idx = Self::mask(ring_buffer.tail);
+            ring_buffer.tail = ring_buffer.tail.wrapping_add(1);
+            Some(unsafe { ring_buffer.buffer.get_unchecked(idx).assume_init_read() })
+        } else {
+            None
+        };
+
+        trace_op!(
+            kind = Self::KIND,
+            evicted = evicted.is_some(),
+            "push_overwrite"
+        );
+        self.put(ring_buffer, value);
+        Ok(evicted)
+    }
+
     pub fn pop(&self) -> Option<T> {
-        let mut ring_buffer = self.0.lock();
-        if ring_buffer.tail != ring_buffer.head {
+        let mut ring_buffer = self.lock();
+        let value = Self::take(&mut ring_buffer);
+        with_metrics!(self.0.metrics.record_poll(value.is_some()));
+        with_metrics!(self.0.metrics.record_occupancy(ring_buffer.len(), N));
+        let value = value?;
+        trace_op!(kind = Self::KIND, occupancy = ring_buffer.len(), "pop");
+        drop(ring_buffer);
+        self.0.popped.notify_one();
+        Some(value)
+    }
+
+    ///Waits for an item instead of returning None on an empty buffer. Returns None only
+    ///once the buffer is closed and everything in it was popped
+    pub fn pop_blocking(&self) -> Option<T> {
+        self.pop_waiting(None)
+    }
+
+    ///Like `pop_blocking` but gives up and returns None once `timeout` has passed.
+    ///Spurious wakeups only wait out what is left of the timeout
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        self.pop_waiting(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_waiting(&self, deadline: Option<Instant>) -> Option<T> {
+        let mut ring_buffer = self.lock();
+        self.wait_while(&self.0.pushed, &mut ring_buffer, deadline, |ring_buffer| {
+            ring_buffer.len() == 0 && !ring_buffer.closed
+        });
+        let value = Self::take(&mut ring_buffer)?;
+        with_metrics!(self.0.metrics.record_occupancy(ring_buffer.len(), N));
+        trace_op!(kind = Self::KIND, occupancy = ring_buffer.len(), "pop");
+        drop(ring_buffer);
+        self.0.popped.notify_one();
+        Some(value)
+    }
+
+    ///Share of pops that found the buffer empty. A ratio close to 1.0 means the consumer
+    ///mostly spins on an empty buffer and would be better off parking
+    #[cfg(feature = "metrics")]
+    pub fn empty_poll_ratio(&self) -> f64 {
+        self.0.metrics.empty_poll_ratio()
+    }
+
+    ///True when the buffer sat above 90% full on average over roughly the last 64 pushes
+    ///and pops, a sign it is too small for the load and will start rejecting pushes.
+    ///Clears again once the consumer catches up
+    #[cfg(feature = "metrics")]
+    pub fn is_chronically_full(&self) -> bool {
+        self.0.metrics.is_chronically_full()
+    }
+
+    ///All metrics counters read in one go with relaxed loads, so they may be slightly
+    ///out of step with each other while the buffer is in use
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> crate::Metrics {
+        self.0.metrics.snapshot()
+    }
+
+    ///Zeroes every metrics counter, returning the values they had right before.
+    ///Each counter is swapped on its own, an operation racing with the reset is counted
+    ///either in the returned values or after the reset, never in both
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&self) -> crate::Metrics {
+        self.0.metrics.reset()
+    }
+
+    ///Swaps `value` in for the item `pop` would return next and returns the old one, the
+    ///length does not change. Returns None and drops nothing if the buffer is empty
+    pub fn replace_front(&self, value: T) -> Option<T> {
+        let mut ring_buffer = self.lock();
+        if ring_buffer.len() == 0 {
+            return None;
+        }
+
+        let front = if O::LIFO {
+            ring_buffer.head.wrapping_sub(1)
+        } else {
+            ring_buffer.tail
+        };
+        let slot = unsafe { ring_buffer.buffer.get_unchecked_mut(Self::mask(front)) };
+        Some(std::mem::replace(unsafe { slot.assume_init_mut() }, value))
+    }
+
+    ///Blocks until at least `threshold` items are buffered (capped at N) or the buffer
+    ///is closed, then moves up to `threshold` items into `out`. Returns how many were moved,
+    ///which is less than `threshold` only when the buffer was closed
+    pub fn pop_when_filled(&self, threshold: usize, out: &mut Vec<T>) -> usize {
+        let threshold = threshold.min(N);
+        let mut ring_buffer = self.lock();
+        self.wait_while(&self.0.pushed, &mut ring_buffer, None, |ring_buffer| {
+            ring_buffer.len() < threshold && !ring_buffer.closed
+        });
+
+        let count = ring_buffer.len().min(threshold);
+        out.reserve(count);
+        for _ in 0..count {
+            if let Some(value) = Self::take(&mut ring_buffer) {
+                out.push(value);
+            }
+        }
+        drop(ring_buffer);
+        if count > 0 {
+            self.0.popped.notify_all();
+        }
+        count
+    }
+
+    ///Pops items one at a time until the buffer is empty, calling `f` on each with the
+    ///lock released. Unlike draining under a single lock this is not a snapshot: producers
+    ///can keep pushing while `f` runs, and whatever they push before the buffer runs dry
+    ///is handed to `f` as well. A producer that outpaces `f` keeps this going indefinitely
+    pub fn drain_for_each(&self, mut f: impl FnMut(T)) {
+        while let Some(value) = self.pop() {
+            f(value);
+        }
+    }
+
+    ///Iterator popping the items that were buffered when it was created, unlike
+    ///`drain_for_each` it stops there even if producers keep pushing. Items taken by
+    ///other consumers in the meantime are skipped. For a `Lifo` buffer it pops those
+    ///items newest first, and newer pushes on top are left in place for `pop`.
+    ///
+    ///The drain remembers where head was and pulls that end back whenever head moves
+    ///back, by `Lifo` pops or `pop_all_where`. It can't tell whether the removed items
+    ///were older or newer than itself, so removing newer ones can make it stop early
+    ///and leave some of its items for `pop`. It never yields an item pushed after it
+    ///was created
+    pub fn drain(&self) -> Drain<'_, T, N, O> {
+        let ring_buffer = self.lock();
+        Drain {
+            buffer: self,
+            end: ring_buffer.head,
+            retreats: ring_buffer.retreats,
+            remaining: ring_buffer.len(),
+        }
+    }
+
+    ///Drops everything buffered, the buffer stays open
+    pub fn clear(&self) {
+        let mut ring_buffer = self.lock();
+        while ring_buffer.tail != ring_buffer.head {
             let idx = Self::mask(ring_buffer.tail);
-            let value;
             unsafe {
-                let ptr = ring_buffer.buffer.get_unchecked(idx).as_ptr();
-
-                value = std::ptr::read(ptr);
+                ring_buffer.buffer.get_unchecked_mut(idx).assume_init_drop();
             }
             ring_buffer.tail = ring_buffer.tail.wrapping_add(1);
-            return Some(value);
         }
+        drop(ring_buffer);
+        self.0.popped.notify_all();
+    }
+
+    ///Closes the buffer, later pushes fail and blocked producers and consumers wake up.
+    ///Items already buffered can still be popped
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.0.pushed.notify_all();
+        self.0.popped.notify_all();
+    }
 
-        None
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    ///Pushes default items until `level` are buffered, for benchmark and test setup.
+    ///Not meant to race with other producers
+    #[doc(hidden)]
+    pub fn fill_to(&self, level: usize)
+    where
+        T: Default,
+    {
+        crate::fill_with_default(N, level, self.len(), |value| self.push(value));
+    }
+
+    ///How many full laps the consumer has made around the storage, total throughput in
+    ///units of whole buffers. Wraps along with the raw tail counter after `usize::MAX` pops.
+    ///A `Lifo` buffer pops from the head end, so for it this stays 0
+    pub fn wrap_count(&self) -> usize {
+        self.lock().tail / N
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Whether a push would succeed right now: open and not full. Advisory with several
+    ///producers, another one can fill the slot first
+    pub fn would_accept(&self) -> bool {
+        let ring_buffer = self.lock();
+        !ring_buffer.closed && ring_buffer.len() < N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    ///Head, tail and the values derived from them, read under one lock
+    pub fn geometry(&self) -> Geometry {
+        let ring_buffer = self.lock();
+        Geometry::new(RingBuffer::<T, N>::MASK, ring_buffer.head, ring_buffer.tail)
+    }
+
+    ///How full the buffer is, from 0.0 (empty) to 1.0 (full)
+    pub fn fill_ratio(&self) -> f64 {
+        self.len() as f64 / N as f64
+    }
+
+    ///Clones everything currently buffered into a Vec in FIFO order, without consuming it
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let ring_buffer = self.lock();
+        let len = ring_buffer.head.wrapping_sub(ring_buffer.tail);
+        let start = Self::mask(ring_buffer.tail);
+        let first = len.min(N - start);
+
+        let mut out = Vec::with_capacity(len);
+        let runs = [start..start + first, 0..len - first];
+        for run in runs {
+            for slot in &ring_buffer.buffer[run] {
+                unsafe {
+                    out.push(slot.assume_init_ref().clone());
+                }
+            }
+        }
+        out
+    }
+
+    ///Calls `f` on every buffered item in FIFO order, in place and under the lock,
+    ///e.g. to age or decay values without popping them
+    pub fn for_each_mut(&self, mut f: impl FnMut(&mut T)) {
+        let mut ring_buffer = self.lock();
+        let (head, mut current) = (ring_buffer.head, ring_buffer.tail);
+        while current != head {
+            let slot = unsafe { ring_buffer.buffer.get_unchecked_mut(Self::mask(current)) };
+            f(unsafe { slot.assume_init_mut() });
+            current = current.wrapping_add(1);
+        }
+    }
+
+    ///Pops every item `pred` matches and returns them oldest first, the opposite of a
+    ///retain. The rest are moved down over the gaps and keep their order. `pred` sees each
+    ///item once, oldest first, under the lock. If it panics the buffer is left compacted
+    ///with everything not popped yet still in it
+    pub fn pop_all_where(&self, mut pred: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut ring_buffer = self.lock();
+        let tail = ring_buffer.tail;
+        let mut compact = Compact {
+            ring_buffer: &mut ring_buffer,
+            read: tail,
+            write: tail,
+        };
+
+        let mut out = Vec::new();
+        while compact.read != compact.ring_buffer.head {
+            let slot = unsafe {
+                compact
+                    .ring_buffer
+                    .buffer
+                    .get_unchecked_mut(Self::mask(compact.read))
+            };
+            if pred(unsafe { slot.assume_init_ref() }) {
+                let value = unsafe { slot.assume_init_read() };
+                compact.read = compact.read.wrapping_add(1);
+                out.push(value);
+            } else {
+                compact.keep();
+            }
+        }
+        drop(compact);
+        drop(ring_buffer);
+
+        if !out.is_empty() {
+            self.0.popped.notify_all();
+        }
+        out
+    }
+
+    ///Panics if the buffer's core invariant is broken: tail never passes head and head
+    ///is never more than N ahead of tail. Meant for tests and fuzzers
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn check_invariants(&self) {
+        let ring_buffer = self.lock();
+        let (head, tail) = (ring_buffer.head, ring_buffer.tail);
+        let len = head.wrapping_sub(tail);
+        assert!(
+            len <= N,
+            "head {head} and tail {tail} are {len} apart, more than N = {N}"
+        );
     }
+
     #[inline(always)]
     fn mask(index: usize) -> usize {
-        index & (N - 1)
+        index & RingBuffer::<T, N>::MASK
+    }
+
+    #[inline]
+    fn take(ring_buffer: &mut RingBuffer<T, N>) -> Option<T> {
+        if O::LIFO {
+            ring_buffer.pop_back()
+        } else {
+            ring_buffer.pop()
+        }
+    }
+
+    ///Writes `value` at the head of a buffer already checked to have room, then wakes
+    ///consumers
+    fn put(&self, mut ring_buffer: MutexGuard<'_, RingBuffer<T, N>>, value: T) {
+        ring_buffer.push_back(value);
+        with_metrics!(self.0.metrics.record_occupancy(ring_buffer.len(), N));
+        trace_op!(
+            kind = Self::KIND,
+            occupancy = ring_buffer.head.wrapping_sub(ring_buffer.tail),
+            "push"
+        );
+        drop(ring_buffer);
+        self.0.pushed.notify_all();
+    }
+
+    ///Waits on `condvar` until `condition` turns false, spinning first if adaptive spin is on.
+    ///Gives up at `deadline` if there is one, returns whether `condition` turned false
+    fn wait_while(
+        &self,
+        condvar: &Condvar,
+        ring_buffer: &mut MutexGuard<'_, RingBuffer<T, N>>,
+        deadline: Option<Instant>,
+        condition: impl Fn(&RingBuffer<T, N>) -> bool,
+    ) -> bool {
+        if self.0.adaptive_spin.load(Ordering::Relaxed) {
+            let mut backoff = Backoff::new();
+            while condition(ring_buffer) && !backoff.is_completed() {
+                MutexGuard::unlocked(ring_buffer, || backoff.snooze());
+            }
+        }
+        while condition(ring_buffer) {
+            match deadline {
+                None => condvar.wait(ring_buffer),
+                //the deadline stays fixed, so waking early just waits out the rest
+                Some(deadline) => {
+                    if condvar.wait_until(ring_buffer, deadline).timed_out() {
+                        return !condition(ring_buffer);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, RingBuffer<T, N>> {
+        self.0.ring_buffer.lock()
+    }
+}
+
+///Compaction state of `pop_all_where`. Slots in `write..read` are holes, dropping it moves
+///the unvisited items down over them and fixes up head, also when `pred` panicked
+struct Compact<'a, T, const N: usize> {
+    ring_buffer: &'a mut RingBuffer<T, N>,
+    read: usize,
+    write: usize,
+}
+
+impl<T, const N: usize> Compact<'_, T, N> {
+    ///Moves the item at `read` down to `write`
+    fn keep(&mut self) {
+        if self.read != self.write {
+            unsafe { self.ring_buffer.move_item(self.read, self.write) };
+        }
+        self.read = self.read.wrapping_add(1);
+        self.write = self.write.wrapping_add(1);
+    }
+}
+
+impl<T, const N: usize> Drop for Compact<'_, T, N> {
+    fn drop(&mut self) {
+        while self.read != self.ring_buffer.head {
+            self.keep();
+        }
+        self.ring_buffer.retreats = self
+            .ring_buffer
+            .retreats
+            .wrapping_add(self.ring_buffer.head.wrapping_sub(self.write));
+        self.ring_buffer.head = self.write;
+    }
+}
+
+///Iterator returned by [`MutexRingBuffer::drain`]. Once it returns None it stays done
+pub struct Drain<'a, T, const N: usize, O: Order = Fifo> {
+    buffer: &'a MutexRingBuffer<T, N, O>,
+    ///Head when the drain was created, only the items below it are ours
+    end: usize,
+    ///The buffer's `retreats` when `end` was taken
+    retreats: usize,
+    ///Items buffered when the drain was created and not yielded yet, an upper bound
+    remaining: usize,
+}
+
+impl<T, const N: usize, O: Order> Iterator for Drain<'_, T, N, O> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let mut ring_buffer = self.buffer.lock();
+        //follow head back, then our items are the ones between tail and end
+        self.end = self
+            .end
+            .wrapping_sub(ring_buffer.retreats.wrapping_sub(self.retreats));
+        let ours = self.end.wrapping_sub(ring_buffer.tail);
+        let value = if ours == 0 || ours > N {
+            None
+        } else if O::LIFO {
+            //newer pushes on top stay, ours is the newest item below them
+            let value = ring_buffer.remove(self.end.wrapping_sub(1));
+            self.end = self.end.wrapping_sub(1);
+            Some(value)
+        } else {
+            ring_buffer.pop()
+        };
+        self.retreats = ring_buffer.retreats;
+        drop(ring_buffer);
+
+        match value {
+            Some(_) => {
+                self.remaining -= 1;
+                self.buffer.0.popped.notify_one();
+            }
+            None => self.remaining = 0,
+        }
+        value
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+impl<T, const N: usize, O: Order> FusedIterator for Drain<'_, T, N, O> {}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    ///Masking a position with this wraps it onto its slot, see `AtomicRingBufferSpsc::MASK`
+    const MASK: usize = N - 1;
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.head.wrapping_sub(self.tail)
+    }
+
+    ///Writes `value` at the head of a buffer already checked to have room
+    fn push_back(&mut self, value: T) {
+        let idx = self.head & Self::MASK;
+        unsafe {
+            self.buffer.get_unchecked_mut(idx).write(value);
+        }
+        self.head = self.head.wrapping_add(1);
+    }
+
+    ///Moves the item at `from` to `to`, leaving `from` logically empty
+    ///
+    ///# Safety
+    ///`from` must hold an item and `to` must be free
+    unsafe fn move_item(&mut self, from: usize, to: usize) {
+        let (from, to) = (from & Self::MASK, to & Self::MASK);
+        unsafe {
+            let value = self.buffer.get_unchecked(from).assume_init_read();
+            self.buffer.get_unchecked_mut(to).write(value);
+        }
+    }
+
+    ///Takes the item at `position`, a buffered one, moving the newer items above it down
+    fn remove(&mut self, position: usize) -> T {
+        let value = unsafe {
+            self.buffer
+                .get_unchecked(position & Self::MASK)
+                .assume_init_read()
+        };
+        let mut hole = position;
+        while hole.wrapping_add(1) != self.head {
+            unsafe { self.move_item(hole.wrapping_add(1), hole) };
+            hole = hole.wrapping_add(1);
+        }
+        self.head = self.head.wrapping_sub(1);
+        self.retreats = self.retreats.wrapping_add(1);
+        value
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.tail == self.head {
+            return None;
+        }
+        let idx = self.tail & Self::MASK;
+        let value;
+        unsafe {
+            let ptr = self.buffer.get_unchecked(idx).as_ptr();
+
+            value = std::ptr::read(ptr);
+        }
+        self.tail = self.tail.wrapping_add(1);
+        Some(value)
+    }
+
+    fn pop_back(&mut self) -> Option<T> {
+        if self.tail == self.head {
+            return None;
+        }
+        self.head = self.head.wrapping_sub(1);
+        self.retreats = self.retreats.wrapping_add(1);
+        let idx = self.head & Self::MASK;
+        unsafe { Some(self.buffer.get_unchecked(idx).assume_init_read()) }
     }
 }
 
 impl<T, const N: usize> Drop for RingBuffer<T, N> {
     fn drop(&mut self) {
-        if std::mem::needs_drop::<T>() {
+        let observer = self.drop_observer.take();
+        if std::mem::needs_drop::<T>() || observer.is_some() {
             while self.tail != self.head {
-                let mask = self.tail & (N - 1);
+                let mask = self.tail & Self::MASK;
                 unsafe {
-                    std::ptr::drop_in_place(self.buffer.get_unchecked_mut(mask).as_mut_ptr());
+                    let slot = self.buffer.get_unchecked_mut(mask);
+                    if let Some(observer) = &observer {
+                        (observer.0)(slot.assume_init_ref());
+                    }
+                    std::ptr::drop_in_place(slot.as_mut_ptr());
                 }
                 self.tail = self.tail.wrapping_add(1);
             }
@@ -86,6 +754,163 @@ impl<T, const N: usize> Drop for RingBuffer<T, N> {
     }
 }
 
+///Writes the buffered items as a sequence, oldest first. The order type is not part of
+///the output
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize, O: Order> serde::Serialize for MutexRingBuffer<T, N, O> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let ring_buffer = self.lock();
+        let mut seq = serializer.serialize_seq(Some(ring_buffer.len()))?;
+        let mut current = ring_buffer.tail;
+        while current != ring_buffer.head {
+            let slot = unsafe { ring_buffer.buffer.get_unchecked(Self::mask(current)) };
+            seq.serialize_element(unsafe { slot.assume_init_ref() })?;
+            current = current.wrapping_add(1);
+        }
+        seq.end()
+    }
+}
+
+///Reads a sequence back into a buffer in the same order, failing if it is longer than N
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, const N: usize, O: Order> serde::Deserialize<'de>
+    for MutexRingBuffer<T, N, O>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T, const N: usize, O>(PhantomData<fn() -> (T, O)>);
+
+        impl<'de, T: serde::Deserialize<'de>, const N: usize, O: Order> serde::de::Visitor<'de>
+            for Visitor<T, N, O>
+        {
+            type Value = MutexRingBuffer<T, N, O>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a sequence of at most {N} items")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let buffer = MutexRingBuffer::new();
+                {
+                    let mut ring_buffer = buffer.lock();
+                    while let Some(value) = seq.next_element()? {
+                        if ring_buffer.len() == N {
+                            return Err(serde::de::Error::invalid_length(N + 1, &self));
+                        }
+                        ring_buffer.push_back(value);
+                    }
+                }
+                Ok(buffer)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor(PhantomData))
+    }
+}
+
+///A mutex protected RingBuffer like [`MutexRingBuffer`] but with its capacity picked at
+///runtime, e.g. from a config file. Always FIFO
+#[derive(Debug, Clone)]
+pub struct DynRingBuffer<T>(Arc<Mutex<DynInner<T>>>);
+
+#[derive(Debug)]
+struct DynInner<T> {
+    head: usize,
+    tail: usize,
+    buffer: Box<[MaybeUninit<T>]>,
+}
+
+impl<T> DynRingBuffer<T> {
+    ///Panics unless `capacity` is a non zero power of two
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity != 0 && capacity.is_power_of_two(),
+            "Buffer capacity {capacity} must be a power of two"
+        );
+        Self(Arc::new(Mutex::new(DynInner {
+            head: 0,
+            tail: 0,
+            buffer: (0..capacity).map(|_| MaybeUninit::uninit()).collect(),
+        })))
+    }
+
+    ///Fails with the value if the buffer is full
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        let mut inner = self.0.lock();
+        if inner.len() == inner.buffer.len() {
+            return Err(PushError::Full(value));
+        }
+        let idx = inner.mask(inner.head);
+        unsafe {
+            inner.buffer.get_unchecked_mut(idx).write(value);
+        }
+        inner.head = inner.head.wrapping_add(1);
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut inner = self.0.lock();
+        if inner.len() == 0 {
+            return None;
+        }
+        let idx = inner.mask(inner.tail);
+        inner.tail = inner.tail.wrapping_add(1);
+        unsafe { Some(inner.buffer.get_unchecked(idx).assume_init_read()) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.0.lock().buffer.len()
+    }
+
+    ///Head, tail and the values derived from them, read under one lock
+    pub fn geometry(&self) -> Geometry {
+        let inner = self.0.lock();
+        Geometry::new(inner.buffer.len() - 1, inner.head, inner.tail)
+    }
+
+    ///Whether a push would succeed right now. Advisory with several producers
+    pub fn would_accept(&self) -> bool {
+        let inner = self.0.lock();
+        inner.len() < inner.buffer.len()
+    }
+}
+
+impl<T> DynInner<T> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.head.wrapping_sub(self.tail)
+    }
+
+    #[inline(always)]
+    fn mask(&self, index: usize) -> usize {
+        index & (self.buffer.len() - 1)
+    }
+}
+
+impl<T> Drop for DynInner<T> {
+    fn drop(&mut self) {
+        if std::mem::needs_drop::<T>() {
+            while self.tail != self.head {
+                let idx = self.mask(self.tail);
+                unsafe { self.buffer.get_unchecked_mut(idx).assume_init_drop() };
+                self.tail = self.tail.wrapping_add(1);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -93,6 +918,13 @@ mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::thread;
 
+    #[test]
+    fn test_kind() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        assert_eq!(buffer.kind(), "mutex-spsc/mpmc");
+        assert_eq!(MutexRingBuffer::<i32, 4>::KIND, "mutex-spsc/mpmc");
+    }
+
     #[test]
     fn test_basic_push_pop_wrap() {
         let buffer = MutexRingBuffer::<i32, 4>::new();
@@ -102,14 +934,14 @@ mod tests {
         assert!(buffer.push(3).is_ok());
         assert!(buffer.push(4).is_ok());
 
-        assert_eq!(buffer.push(5), Err(5));
+        assert_eq!(buffer.push(5), Err(PushError::Full(5)));
         assert_eq!(buffer.pop(), Some(1));
         assert_eq!(buffer.pop(), Some(2));
 
         assert!(buffer.push(5).is_ok());
         assert!(buffer.push(6).is_ok());
 
-        assert_eq!(buffer.push(7), Err(7));
+        assert_eq!(buffer.push(7), Err(PushError::Full(7)));
 
         assert_eq!(buffer.pop(), Some(3));
         assert_eq!(buffer.pop(), Some(4));
@@ -212,6 +1044,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_vec_wrapped() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert_eq!(buffer.pop(), Some(0));
+        assert_eq!(buffer.pop(), Some(1));
+        buffer.push(4).unwrap();
+        buffer.push(5).unwrap();
+
+        assert_eq!(buffer.to_vec(), vec![2, 3, 4, 5]);
+
+        for i in 2..6 {
+            assert_eq!(buffer.pop(), Some(i));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.to_vec().is_empty());
+    }
+
     #[test]
     fn test_zst() {
         struct Zst;
@@ -227,4 +1080,693 @@ mod tests {
         assert!(buffer.pop().is_some());
         assert!(buffer.push(Zst).is_ok());
     }
+
+    #[test]
+    fn test_fill_ratio() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(buffer.fill_ratio(), 0.0);
+        assert!(buffer.is_empty());
+
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.fill_ratio(), 0.5);
+
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.fill_ratio(), 1.0);
+
+        buffer.pop();
+        assert_eq!(buffer.fill_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_pop_when_filled() {
+        let buffer = MutexRingBuffer::<usize, 8>::new();
+        let producer_buffer = buffer.clone();
+
+        let producer = thread::spawn(move || {
+            for i in 0..5 {
+                thread::sleep(std::time::Duration::from_millis(5));
+                producer_buffer.push(i).unwrap();
+            }
+        });
+
+        let mut out = Vec::new();
+        assert_eq!(buffer.pop_when_filled(5, &mut out), 5);
+        assert_eq!(out, vec![0, 1, 2, 3, 4]);
+        assert!(buffer.is_empty());
+
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn test_blocking_pipeline() {
+        const PRODUCERS: usize = 3;
+        const CONSUMERS: usize = 2;
+        const ITEMS: usize = 5_000;
+
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let buffer = buffer.clone();
+                thread::spawn(move || {
+                    for i in 0..ITEMS {
+                        buffer.push_blocking(p * ITEMS + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let buffer = buffer.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    while let Some(value) = buffer.pop_blocking() {
+                        seen.push(value);
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        buffer.close();
+        let mut seen: Vec<usize> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..PRODUCERS * ITEMS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_blocking_ops_return_on_close() {
+        let buffer = MutexRingBuffer::<usize, 2>::new();
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+
+        let producer_buffer = buffer.clone();
+        let producer = thread::spawn(move || producer_buffer.push_blocking(3));
+        thread::sleep(std::time::Duration::from_millis(10));
+        buffer.close();
+        assert_eq!(producer.join().unwrap(), Err(PushError::Closed(3)));
+
+        assert_eq!(buffer.pop_blocking(), Some(1));
+        assert_eq!(buffer.pop_blocking(), Some(2));
+        assert_eq!(buffer.pop_blocking(), None);
+    }
+
+    #[test]
+    fn test_timeouts_give_up() {
+        let timeout = std::time::Duration::from_millis(20);
+        let buffer = MutexRingBuffer::<usize, 2>::new();
+
+        let start = std::time::Instant::now();
+        assert_eq!(buffer.pop_timeout(timeout), None);
+        assert!(start.elapsed() >= timeout);
+
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        let start = std::time::Instant::now();
+        assert_eq!(buffer.push_timeout(3, timeout), Err(PushError::Full(3)));
+        assert!(start.elapsed() >= timeout);
+
+        buffer.close();
+        assert_eq!(buffer.push_timeout(3, timeout), Err(PushError::Closed(3)));
+        assert_eq!(buffer.pop_timeout(timeout), Some(1));
+    }
+
+    #[test]
+    fn test_timeouts_succeed_in_time() {
+        let timeout = std::time::Duration::from_secs(10);
+        let buffer = MutexRingBuffer::<usize, 1>::new();
+        let other = buffer.clone();
+
+        let producer = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(10));
+            other.push(1).unwrap();
+            other.push_timeout(2, timeout)
+        });
+        assert_eq!(buffer.pop_timeout(timeout), Some(1));
+        assert_eq!(buffer.pop_timeout(timeout), Some(2));
+        assert_eq!(producer.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn test_timeout_not_restarted_by_wakeups() {
+        let timeout = std::time::Duration::from_millis(50);
+        let buffer = MutexRingBuffer::<usize, 1>::new();
+        buffer.push(0).unwrap();
+
+        //keeps popping and refilling the only slot, every pop wakes the waiting push
+        let churn = buffer.clone();
+        let done = Arc::new(AtomicBool::new(false));
+        let churn_done = done.clone();
+        let handle = thread::spawn(move || {
+            while !churn_done.load(Ordering::Relaxed) {
+                if let Some(value) = churn.pop() {
+                    churn.push(value).ok();
+                }
+            }
+        });
+
+        let start = std::time::Instant::now();
+        let _ = buffer.push_timeout(1, timeout);
+        assert!(start.elapsed() < timeout * 20);
+        done.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_pop_when_filled_returns_on_close() {
+        let buffer = MutexRingBuffer::<usize, 8>::new();
+        let closer = buffer.clone();
+        buffer.push(1).unwrap();
+
+        let handle = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(10));
+            closer.close();
+        });
+
+        let mut out = Vec::new();
+        assert_eq!(buffer.pop_when_filled(4, &mut out), 1);
+        assert_eq!(out, vec![1]);
+        assert!(buffer.is_closed());
+        assert_eq!(buffer.push(2), Err(PushError::Closed(2)));
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_fifo_and_lifo_order() {
+        let fifo = MutexRingBuffer::<i32, 4>::new();
+        let lifo = MutexRingBuffer::<i32, 4, Lifo>::new();
+        for i in 1..=3 {
+            fifo.push(i).unwrap();
+            lifo.push(i).unwrap();
+        }
+
+        assert_eq!(fifo.pop(), Some(1));
+        assert_eq!(fifo.pop(), Some(2));
+        assert_eq!(fifo.pop(), Some(3));
+        assert_eq!(fifo.pop(), None);
+
+        assert_eq!(lifo.pop(), Some(3));
+        assert_eq!(lifo.pop(), Some(2));
+        lifo.push(4).unwrap();
+        assert_eq!(lifo.pop(), Some(4));
+        assert_eq!(lifo.pop(), Some(1));
+        assert_eq!(lifo.pop(), None);
+    }
+
+    #[test]
+    fn test_lifo_wraps() {
+        let lifo = MutexRingBuffer::<i32, 4, Lifo>::new();
+        for i in 0..4 {
+            lifo.push(i).unwrap();
+        }
+        assert_eq!(lifo.push(4), Err(PushError::Full(4)));
+        for i in (0..4).rev() {
+            assert_eq!(lifo.pop(), Some(i));
+        }
+        for i in 0..6 {
+            lifo.push(i).unwrap();
+            assert_eq!(lifo.pop(), Some(i));
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_empty_poll_ratio() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        assert_eq!(buffer.empty_poll_ratio(), 0.0);
+
+        for i in 0..10 {
+            assert_eq!(buffer.pop(), None);
+            buffer.push(i).unwrap();
+            assert_eq!(buffer.pop(), Some(i));
+        }
+        assert_eq!(buffer.empty_poll_ratio(), 0.5);
+
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.empty_poll_ratio() > 0.5);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        buffer.check_invariants();
+        for i in 0..10 {
+            buffer.push(i).unwrap();
+            buffer.check_invariants();
+            if i % 3 == 0 {
+                buffer.pop();
+                buffer.check_invariants();
+            }
+            if buffer.len() == 4 {
+                buffer.pop();
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "more than N")]
+    fn test_check_invariants_catches_corruption() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        buffer.lock().tail = 1;
+        buffer.check_invariants();
+    }
+
+    #[test]
+    fn test_drain_for_each_lets_producers_in() {
+        let buffer = MutexRingBuffer::<usize, 8>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+
+        let draining = Arc::new(AtomicUsize::new(0));
+        let producer = thread::spawn({
+            let buffer = buffer.clone();
+            let draining = draining.clone();
+            move || {
+                while draining.load(Ordering::Acquire) == 0 {
+                    thread::yield_now();
+                }
+                buffer.push(100).unwrap();
+                draining.store(2, Ordering::Release);
+            }
+        });
+
+        let mut drained = Vec::new();
+        buffer.drain_for_each(|value| {
+            if drained.is_empty() {
+                //the producer has to get the lock while we are still draining
+                draining.store(1, Ordering::Release);
+                while draining.load(Ordering::Acquire) != 2 {
+                    thread::yield_now();
+                }
+            }
+            drained.push(value);
+        });
+
+        producer.join().unwrap();
+        assert_eq!(drained, vec![0, 1, 2, 3, 100]);
+        assert!(buffer.is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_snapshot_and_reset() {
+        let buffer = MutexRingBuffer::<i32, 4>::new();
+        buffer.push(1).unwrap();
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(buffer.metrics_snapshot().hit_polls, 1);
+        assert_eq!(buffer.metrics_snapshot().empty_polls, 1);
+
+        buffer.reset_metrics();
+        assert_eq!(buffer.metrics_snapshot(), crate::Metrics::default());
+    }
+
+    #[test]
+    fn test_replace_front() {
+        let buffer = MutexRingBuffer::<String, 4>::new();
+        assert_eq!(buffer.replace_front("x".into()), None);
+        assert!(buffer.is_empty());
+
+        buffer.push("a".into()).unwrap();
+        buffer.push("b".into()).unwrap();
+        assert_eq!(buffer.replace_front("c".into()), Some("a".into()));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some("c".into()));
+        assert_eq!(buffer.pop(), Some("b".into()));
+
+        let lifo = MutexRingBuffer::<String, 4, Lifo>::new();
+        lifo.push("a".into()).unwrap();
+        lifo.push("b".into()).unwrap();
+        assert_eq!(lifo.replace_front("c".into()), Some("b".into()));
+        assert_eq!(lifo.pop(), Some("c".into()));
+    }
+
+    #[cfg(feature = "test-alloc")]
+    #[test]
+    fn test_hot_path_does_not_allocate() {
+        use crate::test_alloc::allocations_during;
+
+        let buffer = MutexRingBuffer::<u64, 8>::new();
+        let allocations = allocations_during(|| {
+            for round in 0..100 {
+                for i in 0..8 {
+                    buffer.push(round * 8 + i).unwrap();
+                }
+                assert!(buffer.push(0).is_err());
+                while buffer.pop().is_some() {}
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_accepts_pushes_again_after_draining() {
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+        for round in 0..3 {
+            for i in 0..4 {
+                buffer.push(round * 4 + i).unwrap();
+            }
+            assert_eq!(buffer.push(99), Err(PushError::Full(99)));
+            for i in 0..4 {
+                assert_eq!(buffer.pop(), Some(round * 4 + i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_overwrite_evicts_oldest() {
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+        for i in 0..4 {
+            assert_eq!(buffer.push_overwrite(i), Ok(None));
+        }
+        assert_eq!(buffer.push_overwrite(4), Ok(Some(0)));
+        assert_eq!(buffer.push_overwrite(5), Ok(Some(1)));
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.to_vec(), [2, 3, 4, 5]);
+
+        let stack = MutexRingBuffer::<usize, 2, Lifo>::new();
+        stack.push_overwrite(0).unwrap();
+        stack.push_overwrite(1).unwrap();
+        assert_eq!(stack.push_overwrite(2), Ok(Some(0)));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+
+        //told apart from an eviction
+        buffer.close();
+        assert_eq!(buffer.push_overwrite(6), Err(PushError::Closed(6)));
+        assert_eq!(buffer.len(), 4);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_push_overwrite_records_occupancy() {
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+        for i in 0..500 {
+            buffer.push_overwrite(i).unwrap();
+        }
+        assert!(buffer.is_chronically_full());
+    }
+
+    #[test]
+    fn test_push_overwrite_does_not_leak() {
+        let counted = std::rc::Rc::new(());
+        {
+            let buffer = MutexRingBuffer::<std::rc::Rc<()>, 2>::new();
+            for _ in 0..10 {
+                drop(buffer.push_overwrite(counted.clone()));
+            }
+            assert_eq!(std::rc::Rc::strong_count(&counted), 3);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&counted), 1);
+    }
+
+    #[test]
+    fn test_pop_all_where() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted(usize);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let buffer = MutexRingBuffer::<Counted, 8>::new();
+        //start at slot 5 so the items wrap around the storage
+        for i in 0..5 {
+            buffer.push(Counted(i)).ok().unwrap();
+            buffer.pop();
+        }
+        for i in 0..6 {
+            buffer.push(Counted(i)).ok().unwrap();
+        }
+        DROPS.store(0, Ordering::Relaxed);
+
+        let evens = buffer.pop_all_where(|item| item.0 % 2 == 0);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            evens.iter().map(|item| item.0).collect::<Vec<_>>(),
+            [0, 2, 4]
+        );
+        assert_eq!(buffer.len(), 3);
+        drop(evens);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+
+        //the gaps are closed, pushes land right after the kept items
+        buffer.push(Counted(6)).ok().unwrap();
+        let rest: Vec<usize> = std::iter::from_fn(|| buffer.pop().map(|item| item.0)).collect();
+        assert_eq!(rest, [1, 3, 5, 6]);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 7);
+        assert!(buffer.pop_all_where(|_| true).is_empty());
+    }
+
+    #[test]
+    fn test_pop_all_where_panicking_pred_keeps_the_rest() {
+        let buffer = MutexRingBuffer::<String, 8>::new();
+        for i in 0..6 {
+            buffer.push(i.to_string()).unwrap();
+        }
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            buffer.pop_all_where(|item| {
+                assert_ne!(item, "3");
+                item == "0" || item == "2"
+            })
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(buffer.len(), 4);
+        let rest: Vec<String> = std::iter::from_fn(|| buffer.pop()).collect();
+        assert_eq!(rest, ["1", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_drain_stops_at_snapshot() {
+        let buffer = MutexRingBuffer::<usize, 8>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+
+        let mut drain = buffer.drain();
+        assert_eq!(drain.next(), Some(0));
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(drain.collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(buffer.to_vec(), [4]);
+
+        let mut sum = 0;
+        for item in buffer.drain() {
+            sum += item;
+        }
+        assert_eq!(sum, 4);
+        assert_eq!(buffer.drain().next(), None);
+
+        let stack = MutexRingBuffer::<usize, 4, Lifo>::new();
+        for i in 0..3 {
+            stack.push(i).unwrap();
+        }
+        assert_eq!(stack.drain().collect::<Vec<_>>(), [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_drain_skips_later_pushes_wherever_they_land() {
+        //pushes on top of a Lifo drain are left for pop
+        let stack = MutexRingBuffer::<usize, 8, Lifo>::new();
+        for i in 0..3 {
+            stack.push(i).unwrap();
+        }
+        let mut drain = stack.drain();
+        assert_eq!(drain.next(), Some(2));
+        stack.push(10).unwrap();
+        stack.push(11).unwrap();
+        assert_eq!(drain.next(), Some(1));
+        assert_eq!(drain.collect::<Vec<_>>(), [0]);
+        assert_eq!(stack.to_vec(), [10, 11]);
+
+        //popping a newer item pulls the end back too, the drain stops early but never
+        //yields a later push
+        for i in 0..3 {
+            stack.push(i).unwrap();
+        }
+        let mut drain = stack.drain();
+        stack.push(12).unwrap();
+        assert_eq!(stack.pop(), Some(12));
+        assert_eq!(drain.next(), Some(1));
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(drain.next(), Some(11));
+        assert_eq!(drain.next(), Some(10));
+        assert_eq!(drain.next(), None);
+        assert_eq!(stack.to_vec(), [2]);
+
+        //pop_all_where moving head back must not let later pushes in
+        let buffer = MutexRingBuffer::<usize, 8>::new();
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        let mut drain = buffer.drain();
+        assert_eq!(buffer.pop_all_where(|&v| v % 2 == 1), [1, 3]);
+        buffer.push(4).unwrap();
+        buffer.push(5).unwrap();
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(drain.next(), Some(2));
+        assert_eq!(drain.next(), None);
+
+        //fused, even once newer items are popped and old looking ones come back
+        buffer.pop();
+        assert_eq!(drain.next(), None);
+        assert_eq!(buffer.to_vec(), [5]);
+    }
+
+    #[test]
+    fn test_pop_when_filled_same_with_adaptive_spin() {
+        for spin in [false, true] {
+            let buffer = MutexRingBuffer::<usize, 8>::new();
+            buffer.set_adaptive_spin(spin);
+            let producer = buffer.clone();
+
+            let handle = thread::spawn(move || {
+                for i in 0..100 {
+                    while producer.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+                producer.close();
+            });
+
+            let mut out = Vec::new();
+            while buffer.pop_when_filled(4, &mut out) == 4 {}
+            handle.join().unwrap();
+            assert_eq!(out, (0..100).collect::<Vec<_>>(), "spin: {spin}");
+        }
+    }
+
+    #[test]
+    fn test_for_each_mut_wrapped() {
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+        for i in 0..6 {
+            buffer.push(i).unwrap();
+            if i % 2 == 0 {
+                buffer.pop();
+            }
+        }
+        //in slots 3, 0 and 1, across the wrap point
+        assert_eq!(buffer.to_vec(), [3, 4, 5]);
+
+        let mut seen = Vec::new();
+        buffer.for_each_mut(|value| {
+            seen.push(*value);
+            *value += 10;
+        });
+        assert_eq!(seen, [3, 4, 5]);
+        assert_eq!(buffer.to_vec(), [13, 14, 15]);
+        assert_eq!(buffer.pop(), Some(13));
+    }
+
+    #[test]
+    fn test_from_vec_deque() {
+        let mut items: VecDeque<usize> = (2..5).collect();
+        items.push_front(1);
+        let buffer = MutexRingBuffer::<usize, 8>::from_vec_deque(items).unwrap();
+        assert_eq!(buffer.len(), 4);
+        for expected in 1..5 {
+            assert_eq!(buffer.pop(), Some(expected));
+        }
+        assert_eq!(buffer.pop(), None);
+
+        let full = MutexRingBuffer::<usize, 4>::from_vec_deque((1..5).collect()).unwrap();
+        assert_eq!(full.push(5), Err(PushError::Full(5)));
+
+        let items: VecDeque<usize> = (1..6).collect();
+        let rejected = MutexRingBuffer::<usize, 4>::from_vec_deque(items.clone()).unwrap_err();
+        assert_eq!(rejected, items);
+    }
+
+    #[test]
+    fn test_dyn_ring_buffer() {
+        let buffer = DynRingBuffer::<String>::new(4);
+        assert_eq!(buffer.capacity(), 4);
+        for round in 0..3 {
+            for i in 0..4 {
+                buffer.push(format!("{round}-{i}")).unwrap();
+            }
+            assert_eq!(
+                buffer.push("full".to_string()),
+                Err(PushError::Full("full".to_string()))
+            );
+            for i in 0..3 {
+                assert_eq!(buffer.pop(), Some(format!("{round}-{i}")));
+            }
+            assert_eq!(buffer.pop(), Some(format!("{round}-3")));
+            assert!(buffer.is_empty());
+        }
+
+        let counted = Arc::new(());
+        let buffer = DynRingBuffer::new(8);
+        for _ in 0..5 {
+            buffer.push(counted.clone()).unwrap();
+        }
+        buffer.pop();
+        drop(buffer);
+        assert_eq!(Arc::strong_count(&counted), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn test_dyn_ring_buffer_rejects_odd_capacity() {
+        DynRingBuffer::<usize>::new(6);
+    }
+
+    #[test]
+    fn test_would_accept() {
+        let buffer = MutexRingBuffer::<usize, 4>::new();
+        assert!(buffer.would_accept());
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert!(!buffer.would_accept());
+        buffer.pop();
+        assert!(buffer.would_accept());
+        buffer.close();
+        assert!(!buffer.would_accept());
+
+        let dyn_buffer = DynRingBuffer::new(2);
+        dyn_buffer.push(0).unwrap();
+        assert!(dyn_buffer.would_accept());
+        dyn_buffer.push(1).unwrap();
+        assert!(!dyn_buffer.would_accept());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let buffer = MutexRingBuffer::<String, 4>::new();
+        for i in 0..6 {
+            buffer.push(i.to_string()).unwrap();
+            if i % 2 == 0 {
+                buffer.pop();
+            }
+        }
+        //wrapped, only the 3 buffered items are written
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(json, r#"["3","4","5"]"#);
+
+        let restored: MutexRingBuffer<String, 4> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_vec(), buffer.to_vec());
+        restored.push("6".to_string()).unwrap();
+        assert!(restored.push("7".to_string()).is_err());
+
+        let too_long = serde_json::from_str::<MutexRingBuffer<u8, 4>>("[1,2,3,4,5]");
+        assert!(too_long.is_err());
+    }
 }
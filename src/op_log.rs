@@ -0,0 +1,61 @@
+use std::thread::{self, ThreadId};
+
+use crate::AtomicRingBufferMpmc;
+use crate::primitives::Arc;
+
+///Records kept per buffer, once full the oldest ones are dropped
+const LOG_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Push,
+    Pop,
+}
+
+///One item pushed or popped, recorded with the `record` feature. Batch operations like
+///`push_slice`, `pop_slice` or `clear` record one entry per item they move, so replaying
+///the records of a dump in order on a fresh buffer from a single thread, with plain
+///pushes and pops, walks it through the same positions the concurrent run did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpRecord {
+    pub op: Op,
+    pub thread: ThreadId,
+    ///Logical position the item was pushed to or popped from
+    pub seq: usize,
+}
+
+///Lock free log of the operations on a buffer, itself an mpmc buffer
+pub(crate) struct OpLog(Arc<AtomicRingBufferMpmc<OpRecord, LOG_CAPACITY>>);
+
+impl std::fmt::Debug for OpLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpLog").field("len", &self.0.len()).finish()
+    }
+}
+
+impl OpLog {
+    pub(crate) fn new() -> Self {
+        Self(AtomicRingBufferMpmc::new_unrecorded())
+    }
+
+    pub(crate) fn record(&self, op: Op, seq: usize) {
+        let mut record = OpRecord {
+            op,
+            thread: thread::current().id(),
+            seq,
+        };
+        //the log's own traffic would drown out the buffer's trace events
+        #[cfg(feature = "tracing")]
+        let _quiet = tracing::dispatcher::set_default(&tracing::Dispatch::none());
+
+        while let Err(back) = self.0.push(record) {
+            self.0.pop();
+            record = back.into_inner();
+        }
+    }
+
+    ///Takes every record out of the log, oldest first
+    pub(crate) fn dump(&self) -> Vec<OpRecord> {
+        std::iter::from_fn(|| self.0.pop()).collect()
+    }
+}
@@ -0,0 +1,35 @@
+//!Memory orderings used by the atomic buffers. With the `seqcst` feature every one of them
+//!is `SeqCst`, if a bug goes away under the feature a fence is missing somewhere.
+//!Slower, only meant for debugging
+
+use core::sync::atomic::Ordering;
+
+#[cfg(not(feature = "seqcst"))]
+pub(crate) const RELAXED: Ordering = Ordering::Relaxed;
+#[cfg(not(feature = "seqcst"))]
+pub(crate) const ACQUIRE: Ordering = Ordering::Acquire;
+#[cfg(not(feature = "seqcst"))]
+pub(crate) const RELEASE: Ordering = Ordering::Release;
+#[cfg(not(feature = "seqcst"))]
+pub(crate) const ACQ_REL: Ordering = Ordering::AcqRel;
+
+#[cfg(feature = "seqcst")]
+pub(crate) const RELAXED: Ordering = Ordering::SeqCst;
+#[cfg(feature = "seqcst")]
+pub(crate) const ACQUIRE: Ordering = Ordering::SeqCst;
+#[cfg(feature = "seqcst")]
+pub(crate) const RELEASE: Ordering = Ordering::SeqCst;
+#[cfg(feature = "seqcst")]
+pub(crate) const ACQ_REL: Ordering = Ordering::SeqCst;
+
+#[cfg(all(test, feature = "seqcst"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seqcst_everywhere() {
+        for ordering in [RELAXED, ACQUIRE, RELEASE, ACQ_REL] {
+            assert_eq!(ordering, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+///A reusable landing spot for batch pops. Each drain clears it first but keeps its
+///capacity, so a consumer draining in a loop stops allocating once it has seen its
+///largest batch
+#[derive(Debug, Clone, Default)]
+pub struct PopBuffer<T> {
+    items: Vec<T>,
+}
+
+impl<T> PopBuffer<T> {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    ///Preallocates room for `capacity` items, e.g. the capacity of the buffer drained into it
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            items: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    ///Takes the items out, keeping the allocation for the next drain
+    pub fn drain(&mut self) -> alloc::vec::Drain<'_, T> {
+        self.items.drain(..)
+    }
+
+    pub fn into_inner(self) -> Vec<T> {
+        self.items
+    }
+
+    pub(crate) fn vec_mut(&mut self) -> &mut Vec<T> {
+        &mut self.items
+    }
+}
+
+impl<T> Deref for PopBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
@@ -1,11 +1,26 @@
-use std::ops::Deref;
-use std::ptr::NonNull;
-use std::sync::atomic::{AtomicUsize, Ordering, fence};
+use alloc::alloc::{Layout, alloc, handle_alloc_error};
+use alloc::boxed::Box;
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering, fence};
+
+///Refcount overflow is unrecoverable, without std there is no abort so panic instead
+#[cold]
+fn abort() -> ! {
+    #[cfg(feature = "std")]
+    std::process::abort();
+    #[cfg(not(feature = "std"))]
+    panic!("Arc refcount overflow");
+}
 
 #[repr(align(64))]
 struct ArcData<T> {
     ref_count: AtomicUsize,
-    data: T,
+    ///Weak handles plus one shared by all the strong ones, so the allocation outlives the
+    ///data while any Weak is around. usize::MAX while `get_mut` checks for uniqueness
+    weak_count: AtomicUsize,
+    data: ManuallyDrop<T>,
 }
 
 //A primitive implemenation of ARC
@@ -23,23 +38,115 @@ impl<T> Arc<T> {
         Arc {
             ptr: NonNull::from(Box::leak(Box::new(ArcData {
                 ref_count: AtomicUsize::new(1),
-                data,
+                weak_count: AtomicUsize::new(1),
+                data: ManuallyDrop::new(data),
             }))),
         }
     }
+    ///Allocates the Arc and lets `init` write the value straight into it, so a large `T`
+    ///never has to exist on the stack.
+    ///
+    ///# Safety
+    ///
+    ///`init` must fully initialize the `T` behind the pointer it is given.
+    pub unsafe fn new_in_place(init: impl FnOnce(*mut T)) -> Arc<T> {
+        let layout = Layout::new::<ArcData<T>>();
+        unsafe {
+            let ptr = alloc(layout) as *mut ArcData<T>;
+            let Some(ptr) = NonNull::new(ptr) else {
+                handle_alloc_error(layout);
+            };
+            (&raw mut (*ptr.as_ptr()).ref_count).write(AtomicUsize::new(1));
+            (&raw mut (*ptr.as_ptr()).weak_count).write(AtomicUsize::new(1));
+            init((&raw mut (*ptr.as_ptr()).data).cast::<T>());
+            Arc { ptr }
+        }
+    }
     #[inline]
     fn data(&self) -> &ArcData<T> {
         unsafe { self.ptr.as_ref() }
     }
+    ///Only succeeds if this is the only Arc and no Weak exists either
     #[inline]
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
-        if arc.data().ref_count.load(Ordering::Relaxed) == 1 {
-            fence(Ordering::Acquire);
+        //locking the weak count stops a Weak being created while we check the strong one
+        if arc
+            .data()
+            .weak_count
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let unique = arc.data().ref_count.load(Ordering::Acquire) == 1;
+        arc.data().weak_count.store(1, Ordering::Release);
+        if unique {
             unsafe { Some(&mut arc.ptr.as_mut().data) }
         } else {
             None
         }
     }
+    ///How many Arcs point to the value, including this one. A snapshot, other threads
+    ///may clone or drop handles right after it is read
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().ref_count.load(Ordering::Acquire)
+    }
+    ///How many Weaks point to the value. A snapshot, it may be stale immediately
+    pub fn weak_count(arc: &Self) -> usize {
+        match arc.data().weak_count.load(Ordering::Acquire) {
+            //locked by get_mut, which only happens while there is no Weak
+            usize::MAX => 0,
+            count => count - 1,
+        }
+    }
+    ///Moves the value out if this is the only Arc, otherwise gives the Arc back.
+    ///Weaks can not upgrade afterwards, like after the last Arc was dropped
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        if arc
+            .data()
+            .ref_count
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+        fence(Ordering::Acquire);
+
+        let arc = ManuallyDrop::new(arc);
+        let data = unsafe { ManuallyDrop::take(&mut (*arc.ptr.as_ptr()).data) };
+        //the weak reference all the strong ones shared, frees the allocation if last
+        drop(Weak { ptr: arc.ptr });
+        Ok(data)
+    }
+    ///Whether both point to the same allocation, contents are not compared
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptr == b.ptr
+    }
+    ///A handle that does not keep the value alive, see [`Weak::upgrade`]
+    pub fn downgrade(arc: &Self) -> Weak<T> {
+        let weak_count = &arc.data().weak_count;
+        let mut current = weak_count.load(Ordering::Relaxed);
+        loop {
+            //get_mut holds the count locked for a moment
+            if current == usize::MAX {
+                core::hint::spin_loop();
+                current = weak_count.load(Ordering::Relaxed);
+                continue;
+            }
+            if current > usize::MAX / 2 {
+                abort();
+            }
+            match weak_count.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Weak { ptr: arc.ptr },
+                Err(actual) => current = actual,
+            }
+        }
+    }
 }
 impl<T> Deref for Arc<T> {
     type Target = T;
@@ -52,7 +159,7 @@ impl<T> Clone for Arc<T> {
     #[inline]
     fn clone(&self) -> Self {
         if self.data().ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+            abort();
         }
         Arc { ptr: self.ptr }
     }
@@ -61,6 +168,67 @@ impl<T> Drop for Arc<T> {
     #[inline]
     fn drop(&mut self) {
         if self.data().ref_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            unsafe {
+                ManuallyDrop::drop(&mut self.ptr.as_mut().data);
+            }
+            //the weak reference all the strong ones shared
+            drop(Weak { ptr: self.ptr });
+        }
+    }
+}
+
+///Non owning handle to the value of an [`Arc`], it keeps the allocation but not the value alive
+#[derive(Debug)]
+pub struct Weak<T> {
+    ptr: NonNull<ArcData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for Weak<T> {}
+unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+
+impl<T> Weak<T> {
+    #[inline]
+    fn data(&self) -> &ArcData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+    ///An Arc to the value if it is still alive
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        let ref_count = &self.data().ref_count;
+        let mut current = ref_count.load(Ordering::Relaxed);
+        loop {
+            //once the count hit zero the value is gone for good, never resurrect it
+            if current == 0 {
+                return None;
+            }
+            if current > usize::MAX / 2 {
+                abort();
+            }
+            match ref_count.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Arc { ptr: self.ptr }),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+impl<T> Clone for Weak<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        if self.data().weak_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            abort();
+        }
+        Weak { ptr: self.ptr }
+    }
+}
+impl<T> Drop for Weak<T> {
+    #[inline]
+    fn drop(&mut self) {
+        if self.data().weak_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
             unsafe {
                 drop(Box::from_raw(self.ptr.as_ptr()));
@@ -97,3 +265,141 @@ fn test() {
 
     assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
 }
+
+#[test]
+fn test_weak() {
+    static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct DetectDrop;
+
+    impl Drop for DetectDrop {
+        fn drop(&mut self) {
+            NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let x = Arc::new(("hello", DetectDrop));
+    let y = Arc::downgrade(&x);
+    let z = Arc::downgrade(&x);
+
+    let t = std::thread::spawn(move || {
+        let y = y.upgrade().unwrap();
+        assert_eq!(y.0, "hello");
+    });
+    t.join().unwrap();
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+
+    //the value goes with the last Arc, not with the last Weak
+    drop(x);
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    assert!(z.upgrade().is_none());
+    drop(z);
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_get_mut_with_weak() {
+    let mut x = Arc::new(3);
+    *Arc::get_mut(&mut x).unwrap() = 4;
+    assert_eq!(*x, 4);
+
+    let y = x.clone();
+    assert!(Arc::get_mut(&mut x).is_none());
+    drop(y);
+    assert!(Arc::get_mut(&mut x).is_some());
+
+    let weak = Arc::downgrade(&x);
+    assert!(Arc::get_mut(&mut x).is_none());
+    drop(weak);
+    assert!(Arc::get_mut(&mut x).is_some());
+}
+
+#[test]
+fn test_weak_upgrade_races_last_drop() {
+    static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct DetectDrop(usize);
+
+    impl Drop for DetectDrop {
+        fn drop(&mut self) {
+            NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    const ROUNDS: usize = 1000;
+    for round in 0..ROUNDS {
+        let x = Arc::new(DetectDrop(round));
+        let weak = Arc::downgrade(&x);
+
+        let t = std::thread::spawn(move || {
+            //either too late, or the value stays alive until this Arc is dropped
+            if let Some(strong) = weak.upgrade() {
+                std::thread::yield_now();
+                assert_eq!(strong.0, round);
+            }
+            weak
+        });
+        drop(x);
+        let weak = t.join().unwrap();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), round + 1);
+    }
+}
+
+#[test]
+fn test_counts() {
+    let x = Arc::new(0);
+    assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (1, 0));
+
+    let y = x.clone();
+    let weak = Arc::downgrade(&x);
+    let weak_clone = weak.clone();
+    assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (2, 2));
+
+    drop(y);
+    drop(weak);
+    assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (1, 1));
+
+    let z = weak_clone.upgrade().unwrap();
+    assert_eq!(Arc::strong_count(&z), 2);
+}
+
+#[test]
+fn test_try_unwrap() {
+    static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct DetectDrop(&'static str);
+
+    impl Drop for DetectDrop {
+        fn drop(&mut self) {
+            NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let x = Arc::new(DetectDrop("hello"));
+    let y = x.clone();
+    let Err(x) = Arc::try_unwrap(x) else {
+        panic!("unwrapped while a clone is alive");
+    };
+    assert_eq!(x.0, "hello");
+    drop(y);
+
+    let weak = Arc::downgrade(&x);
+    let value = Arc::try_unwrap(x).ok().unwrap();
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(value.0, "hello");
+
+    drop(value);
+    drop(weak);
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_ptr_eq() {
+    let x = Arc::new(5);
+    let y = x.clone();
+    let z = Arc::new(5);
+    assert!(Arc::ptr_eq(&x, &y));
+    assert!(!Arc::ptr_eq(&x, &z));
+}
@@ -0,0 +1,303 @@
+//!Hazard pointers for the linked structures. A thread that unlinks a node can't free it
+//!right away, another thread may have loaded a pointer to it just before and still be
+//!reading it. So every operation publishes the nodes it is about to read in a record
+//!first, and checks they are still linked afterwards. An unlinked node goes on the
+//!retired list of the operation that unlinked it, and once that list is long enough
+//!it is scanned and every node no record points to is freed.
+//!
+//!Since a node isn't freed while anyone holds a hazard on it, its address can't be
+//!handed out again either, which is what rules out ABA on the head CAS.
+//!A scan keeps at most the nodes that are protected right then, so retired nodes stay
+//!bounded by the number of records times `SCAN_BATCH`, however the operations overlap.
+//!Used by [`TreiberStack`](crate::TreiberStack) and [`MsQueue`](crate::MsQueue)
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering::SeqCst};
+#[cfg(test)]
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+///Nodes an operation can protect at once, the MS queue needs head and its next
+const HAZARDS: usize = 2;
+///Length of a record's retired list that triggers a scan. Has to stay above the hazards
+///of a handful of records, or every scan keeps the list full and runs again
+pub(crate) const SCAN_BATCH: usize = 64;
+
+///One per operation in flight, reused by the next operation once it is done. Records are
+///never freed before the reclaimer, so the list can be walked without protection
+#[repr(align(64))]
+struct Record<N> {
+    in_use: AtomicBool,
+    hazards: [AtomicPtr<N>; HAZARDS],
+    ///Only touched by whoever holds the record
+    retired: UnsafeCell<Vec<*mut N>>,
+    ///Set before the record is published and never changed
+    next: *mut Record<N>,
+}
+
+///An operation in flight, hand it back to [`Reclaimer::leave`]
+pub(crate) struct Guard<'a, N> {
+    record: &'a Record<N>,
+}
+
+impl<N> Guard<'_, N> {
+    ///Loads `source` and protects what it points to. The node is safe to read until
+    ///`leave`, or until the slot protects something else
+    #[inline]
+    pub(crate) fn protect_load(&self, slot: usize, source: &AtomicPtr<N>) -> *mut N {
+        let mut node = source.load(SeqCst);
+        loop {
+            self.record.hazards[slot].store(node, SeqCst);
+            //still linked after the hazard went up, so no scan can have missed it
+            let current = source.load(SeqCst);
+            if current == node {
+                return node;
+            }
+            node = current;
+        }
+    }
+
+    ///Protects a node loaded some other way. The caller has to check it is still
+    ///reachable afterwards before reading it
+    #[inline]
+    pub(crate) fn protect(&self, slot: usize, node: *mut N) {
+        self.record.hazards[slot].store(node, SeqCst);
+    }
+}
+
+///All of it is `SeqCst`: a scan has to see a hazard that went up before the reader
+///checked its node was still linked. The data structures load and CAS their links with
+///`SeqCst` as well for the same reason
+pub(crate) struct Reclaimer<N> {
+    records: AtomicPtr<Record<N>>,
+    ///Nodes retired and not freed yet, and the most there ever were
+    #[cfg(test)]
+    pending: AtomicUsize,
+    #[cfg(test)]
+    max_pending: AtomicUsize,
+}
+
+impl<N> Reclaimer<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            records: AtomicPtr::new(ptr::null_mut()),
+            #[cfg(test)]
+            pending: AtomicUsize::new(0),
+            #[cfg(test)]
+            max_pending: AtomicUsize::new(0),
+        }
+    }
+
+    ///Call before loading any link, pair with `leave`
+    #[inline]
+    pub(crate) fn enter(&self) -> Guard<'_, N> {
+        let mut record = self.records.load(SeqCst);
+        while !record.is_null() {
+            let candidate = unsafe { &*record };
+            if !candidate.in_use.load(SeqCst)
+                && candidate
+                    .in_use
+                    .compare_exchange(false, true, SeqCst, SeqCst)
+                    .is_ok()
+            {
+                return Guard { record: candidate };
+            }
+            record = candidate.next;
+        }
+        Guard {
+            record: self.add_record(),
+        }
+    }
+
+    ///Every record is taken, so this operation brings its own
+    fn add_record(&self) -> &Record<N> {
+        let record = Box::into_raw(Box::new(Record {
+            in_use: AtomicBool::new(true),
+            hazards: [const { AtomicPtr::new(ptr::null_mut()) }; HAZARDS],
+            retired: UnsafeCell::new(Vec::new()),
+            next: ptr::null_mut(),
+        }));
+
+        let mut head = self.records.load(SeqCst);
+        loop {
+            //not published yet, nobody else can see the record
+            unsafe { (*record).next = head };
+            match self
+                .records
+                .compare_exchange_weak(head, record, SeqCst, SeqCst)
+            {
+                Ok(_) => return unsafe { &*record },
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    ///Call once done with every pointer loaded since `enter`, with the node this
+    ///operation unlinked if any.
+    ///
+    ///# Safety
+    ///
+    ///`unlinked` must come from `Box::into_raw`, be unreachable from the data structure
+    ///and be handed here exactly once
+    pub(crate) unsafe fn leave(&self, guard: Guard<'_, N>, unlinked: Option<*mut N>) {
+        let record = guard.record;
+        for hazard in &record.hazards {
+            hazard.store(ptr::null_mut(), SeqCst);
+        }
+
+        if let Some(node) = unlinked {
+            let retired = unsafe { &mut *record.retired.get() };
+            retired.push(node);
+            #[cfg(test)]
+            {
+                let pending = self.pending.fetch_add(1, Relaxed) + 1;
+                self.max_pending.fetch_max(pending, Relaxed);
+            }
+            if retired.len() >= SCAN_BATCH {
+                self.scan(retired);
+            }
+        }
+
+        record.in_use.store(false, SeqCst);
+    }
+
+    ///Frees every node in `retired` that no record protects
+    fn scan(&self, retired: &mut Vec<*mut N>) {
+        let mut protected = Vec::new();
+        let mut record = self.records.load(SeqCst);
+        while !record.is_null() {
+            let current = unsafe { &*record };
+            for hazard in &current.hazards {
+                let node = hazard.load(SeqCst);
+                if !node.is_null() {
+                    protected.push(node);
+                }
+            }
+            record = current.next;
+        }
+
+        retired.retain(|&node| {
+            if protected.contains(&node) {
+                return true;
+            }
+            //unlinked before we looked, anyone who protects it from now on fails their check
+            drop(unsafe { Box::from_raw(node) });
+            #[cfg(test)]
+            self.pending.fetch_sub(1, Relaxed);
+            false
+        });
+    }
+
+    ///Most nodes that were ever waiting to be freed at once
+    #[cfg(test)]
+    pub(crate) fn max_pending(&self) -> usize {
+        self.max_pending.load(Relaxed)
+    }
+
+    ///Records handed out so far, retired nodes are bounded by this times `SCAN_BATCH`
+    #[cfg(test)]
+    pub(crate) fn records(&self) -> usize {
+        let mut count = 0;
+        let mut record = self.records.load(SeqCst);
+        while !record.is_null() {
+            count += 1;
+            record = unsafe { (*record).next };
+        }
+        count
+    }
+}
+
+impl<N> Drop for Reclaimer<N> {
+    fn drop(&mut self) {
+        let mut record = *self.records.get_mut();
+        while !record.is_null() {
+            let boxed = unsafe { Box::from_raw(record) };
+            for &node in unsafe { &*boxed.retired.get() } {
+                drop(unsafe { Box::from_raw(node) });
+            }
+            record = boxed.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    ///Counts as 1 when freed. Not zero sized, those would all share one address and
+    ///look protected
+    struct Node(usize);
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            FREED.fetch_add(self.0, Ordering::Relaxed);
+        }
+    }
+
+    fn node() -> *mut Node {
+        Box::into_raw(Box::new(Node(1)))
+    }
+
+    #[test]
+    fn test_protected_node_survives_scans() {
+        FREED.store(0, Ordering::Relaxed);
+        {
+            let reclaimer = Reclaimer::<Node>::new();
+            let shared = AtomicPtr::new(node());
+
+            let reader = reclaimer.enter();
+            let protected = reader.protect_load(0, &shared);
+
+            //unlinked and retired by someone else, then enough retirements to scan
+            let writer = reclaimer.enter();
+            unsafe { reclaimer.leave(writer, Some(protected)) };
+            for _ in 1..SCAN_BATCH {
+                let writer = reclaimer.enter();
+                unsafe { reclaimer.leave(writer, Some(node())) };
+            }
+            //everything but the protected node went
+            assert_eq!(FREED.load(Ordering::Relaxed), SCAN_BATCH - 1);
+
+            unsafe { reclaimer.leave(reader, None) };
+            for _ in 0..SCAN_BATCH - 1 {
+                let writer = reclaimer.enter();
+                unsafe { reclaimer.leave(writer, Some(node())) };
+            }
+            assert_eq!(FREED.load(Ordering::Relaxed), 2 * SCAN_BATCH - 1);
+
+            let writer = reclaimer.enter();
+            unsafe { reclaimer.leave(writer, Some(node())) };
+            //never scanned, drop cleans up
+        }
+        assert_eq!(FREED.load(Ordering::Relaxed), 2 * SCAN_BATCH);
+    }
+
+    #[test]
+    fn test_bounded_under_constant_overlap() {
+        const OPS: usize = 100_000;
+
+        let reclaimer = Reclaimer::<u64>::new();
+        let shared = AtomicPtr::new(Box::into_raw(Box::new(0)));
+
+        //two operations leapfrog each other, one is always in flight when the other
+        //leaves, so there is never a moment where nobody could be reading
+        let mut running = reclaimer.enter();
+        for i in 1..=OPS as u64 {
+            let next = reclaimer.enter();
+            next.protect_load(0, &shared);
+            let unlinked = shared.swap(Box::into_raw(Box::new(i)), SeqCst);
+            unsafe { reclaimer.leave(running, Some(unlinked)) };
+            running = next;
+        }
+        unsafe { reclaimer.leave(running, None) };
+
+        assert_eq!(reclaimer.records(), 2);
+        assert!(reclaimer.max_pending() <= 2 * SCAN_BATCH);
+        drop(unsafe { Box::from_raw(shared.load(SeqCst)) });
+    }
+}
@@ -17,6 +17,20 @@ pub struct Simulation {
     producer_acc: f64,
     consumer_acc: f64,
     item_counter: u32,
+    produced: u32,
+    consumed: u32,
+    dropped: u32,
+}
+
+///Totals of a run, see [`Simulation::finalize`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RunSummary {
+    produced: u32,
+    consumed: u32,
+    ///Pushes rejected because the buffer was full
+    dropped: u32,
+    ///Items still buffered at the end, drained without counting as consumed
+    final_occupancy: u32,
 }
 
 impl Default for Simulation {
@@ -36,6 +50,9 @@ impl Simulation {
             producer_acc: 0.0,
             consumer_acc: 0.0,
             item_counter: 0,
+            produced: 0,
+            consumed: 0,
+            dropped: 0,
         }
     }
 
@@ -57,18 +74,61 @@ impl Simulation {
         self.producer_acc += producer_speed;
         while self.producer_acc >= 1.0 {
             self.item_counter = self.item_counter.wrapping_add(1);
-            self.buffer.push(self.item_counter).ok();
+            self.produced += 1;
+            if self.buffer.push(self.item_counter).is_err() {
+                self.dropped += 1;
+            }
             self.producer_acc -= 1.0;
         }
 
         self.consumer_acc += consumer_speed;
         while self.consumer_acc >= 1.0 {
-            self.buffer.pop();
+            if self.buffer.pop().is_some() {
+                self.consumed += 1;
+            }
             self.consumer_acc -= 1.0;
         }
 
         self.draw();
     }
+    ///Ends the run: drains the buffer, resets the counters and returns
+    ///`{ produced, consumed, dropped, finalOccupancy }` where produced is always the sum
+    ///of the other three
+    pub fn finalize(&mut self) -> JsValue {
+        let summary = self.finish();
+        let object = js_sys::Object::new();
+        for (key, value) in [
+            ("produced", summary.produced),
+            ("consumed", summary.consumed),
+            ("dropped", summary.dropped),
+            ("finalOccupancy", summary.final_occupancy),
+        ] {
+            js_sys::Reflect::set(&object, &JsValue::from_str(key), &JsValue::from(value)).unwrap();
+        }
+        self.draw();
+        object.into()
+    }
+
+    fn finish(&mut self) -> RunSummary {
+        let mut final_occupancy = 0;
+        while self.buffer.pop().is_some() {
+            final_occupancy += 1;
+        }
+        let summary = RunSummary {
+            produced: self.produced,
+            consumed: self.consumed,
+            dropped: self.dropped,
+            final_occupancy,
+        };
+
+        self.producer_acc = 0.0;
+        self.consumer_acc = 0.0;
+        self.produced = 0;
+        self.consumed = 0;
+        self.dropped = 0;
+        summary
+    }
+
     #[allow(deprecated)]
     fn draw(&self) {
         if let Some(ctx) = &self.canvas {
@@ -79,22 +139,23 @@ impl Simulation {
             let center_y = self.height / 2.0;
             let radius = 150.0;
             let slot_radius = 15.0;
+            let geometry = self.buffer.geometry();
 
             for i in 0..CAPACITY {
                 let angle = (i as f64 / CAPACITY as f64) * 2.0 * f64::consts::PI;
                 let x = center_x + radius * angle.cos();
                 let y = center_y + radius * angle.sin();
 
-                let color = if self.buffer.exists(i) {
+                let color = if geometry.is_occupied(i) {
                     "#ff4d4d"
                 } else {
                     "#4dff88"
                 };
 
-                if i == self.buffer.read_head() {
+                if i == geometry.head_masked {
                     ctx.set_stroke_style(&JsValue::from_str("white"));
                     ctx.set_line_width(4.0);
-                } else if i == self.buffer.read_tail() {
+                } else if i == geometry.tail_masked {
                     ctx.set_stroke_style(&JsValue::from_str("yellow"));
                     ctx.set_line_width(4.0);
                 } else {
@@ -192,3 +253,31 @@ impl Simulation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finish_counts_add_up() {
+        let mut simulation = Simulation::new();
+        //the producer outpaces the consumer so the buffer fills and drops
+        for _ in 0..40 {
+            simulation.tick(2.0, 0.5);
+        }
+
+        let summary = simulation.finish();
+        assert_eq!(summary.produced, 80);
+        assert_eq!(summary.consumed, 20);
+        assert!(summary.dropped > 0);
+        assert_eq!(
+            summary.produced,
+            summary.consumed + summary.dropped + summary.final_occupancy
+        );
+        assert!(simulation.buffer.is_empty());
+
+        simulation.tick(1.0, 0.0);
+        let summary = simulation.finish();
+        assert_eq!((summary.produced, summary.final_occupancy), (1, 1));
+    }
+}
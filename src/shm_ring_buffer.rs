@@ -0,0 +1,239 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicUsize;
+
+use crate::ordering::{ACQUIRE, RELAXED, RELEASE};
+use crate::{Geometry, Padded, TryPushError};
+
+///A pointer free spsc ring buffer for shared memory, all of its state lives inline so
+///it can be placed in a memory mapped region and used from two processes.
+///`T` itself must not contain pointers (no Box, Vec, String, references...) since an
+///address in one process means nothing in the other!
+#[repr(C)]
+pub struct ShmRingBuffer<T, const N: usize> {
+    head: Padded<AtomicUsize>,
+    tail: Padded<AtomicUsize>,
+    buffer: UnsafeCell<[MaybeUninit<T>; N]>,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for ShmRingBuffer<T, N> {}
+
+impl<T, const N: usize> Default for ShmRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> ShmRingBuffer<T, N> {
+    ///Masking a position with this wraps it onto its slot, see `AtomicRingBufferSpsc::MASK`
+    const MASK: usize = N - 1;
+
+    pub const fn new() -> Self {
+        const {
+            assert!(
+                N != 0 && N.is_power_of_two(),
+                "Buffer size N must be a power of two"
+            )
+        };
+        Self {
+            head: Padded(AtomicUsize::new(0)),
+            tail: Padded(AtomicUsize::new(0)),
+            buffer: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+        }
+    }
+
+    ///Initializes a buffer at `ptr`, e.g. the start of a freshly mapped region.
+    ///
+    ///# Safety
+    ///
+    ///`ptr` must be non null, aligned to `align_of::<Self>()` and valid for writes of
+    ///`size_of::<Self>()` bytes for as long as the buffer is used. Whatever was there
+    ///is overwritten without being dropped.
+    pub unsafe fn new_in_place(ptr: *mut Self) {
+        unsafe {
+            (&raw mut (*ptr).head).write(Padded(AtomicUsize::new(0)));
+            (&raw mut (*ptr).tail).write(Padded(AtomicUsize::new(0)));
+        }
+    }
+
+    ///Only one producer may push at a time
+    pub fn push(&self, value: T) -> Result<(), TryPushError<T>> {
+        let head = self.head.load(RELAXED);
+        let tail = self.tail.load(ACQUIRE);
+
+        if head.wrapping_sub(tail) == N {
+            return Err(TryPushError::Full(value));
+        }
+
+        unsafe {
+            let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
+            (*buffer_ptr.add(head & Self::MASK)).write(value);
+        }
+
+        self.head.store(head.wrapping_add(1), RELEASE);
+        Ok(())
+    }
+
+    ///Only one consumer may pop at a time
+    pub fn pop(&self) -> Option<T> {
+        let tail = self.tail.load(RELAXED);
+        let head = self.head.load(ACQUIRE);
+
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe {
+            let buffer_ptr = self.buffer.get() as *mut MaybeUninit<T>;
+            (*buffer_ptr.add(tail & Self::MASK)).assume_init_read()
+        };
+
+        self.tail.store(tail.wrapping_add(1), RELEASE);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(ACQUIRE);
+        self.head.load(ACQUIRE).wrapping_sub(tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Whether a push would find a free slot right now. Exact for the producer, pops
+    ///racing with it only free more slots
+    pub fn would_accept(&self) -> bool {
+        self.len() < N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    ///Panics if the buffer's core invariant is broken: tail never passes head and head
+    ///is never more than N ahead of tail. Worth running on a region mapped in from
+    ///another process before trusting it. Meant to be called while no push or pop is
+    ///running
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn check_invariants(&self) {
+        let head = self.head.load(ACQUIRE);
+        let tail = self.tail.load(ACQUIRE);
+        let len = head.wrapping_sub(tail);
+        assert!(
+            len <= N,
+            "head {head} and tail {tail} are {len} apart, more than N = {N}"
+        );
+    }
+
+    ///Head, tail and the values derived from them, from a single consistent read
+    pub fn geometry(&self) -> Geometry {
+        loop {
+            let tail = self.tail.load(ACQUIRE);
+            let head = self.head.load(ACQUIRE);
+            if self.tail.load(ACQUIRE) == tail {
+                return Geometry::new(Self::MASK, head, tail);
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for ShmRingBuffer<T, N> {
+    fn drop(&mut self) {
+        if core::mem::needs_drop::<T>() {
+            while self.pop().is_some() {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::Ordering;
+    use std::thread;
+
+    #[test]
+    fn test_simple_push_pop() {
+        let buffer = ShmRingBuffer::<u32, 2>::new();
+
+        assert!(buffer.push(1).is_ok());
+        assert!(buffer.push(2).is_ok());
+        assert_eq!(buffer.push(3), Err(TryPushError::Full(3)));
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let buffer = ShmRingBuffer::<i32, 4>::new();
+        buffer.check_invariants();
+        for i in 0..10 {
+            buffer.push(i).unwrap();
+            buffer.check_invariants();
+            if i % 3 == 0 {
+                buffer.pop();
+                buffer.check_invariants();
+            }
+            if buffer.len() == 4 {
+                buffer.pop();
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "more than N")]
+    fn test_check_invariants_catches_corruption() {
+        let buffer = ShmRingBuffer::<i32, 4>::new();
+        buffer.head.store(5, Ordering::Relaxed);
+        buffer.check_invariants();
+    }
+
+    #[test]
+    fn test_shared_through_raw_pointer() {
+        const COUNT: u64 = 10_000;
+
+        let region = Box::into_raw(Box::new(ShmRingBuffer::<u64, 16>::new()));
+        let address = region as usize;
+
+        let producer = thread::spawn(move || {
+            let buffer = unsafe { &*(address as *const ShmRingBuffer<u64, 16>) };
+            for i in 0..COUNT {
+                while buffer.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let consumer = thread::spawn(move || {
+            let buffer = unsafe { &*(address as *const ShmRingBuffer<u64, 16>) };
+            for i in 0..COUNT {
+                loop {
+                    if let Some(value) = buffer.pop() {
+                        assert_eq!(value, i);
+                        break;
+                    }
+                    thread::yield_now();
+                }
+            }
+        });
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+
+        let buffer = unsafe { Box::from_raw(region) };
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_would_accept() {
+        let buffer = ShmRingBuffer::<usize, 4>::new();
+        assert!(buffer.would_accept());
+        for i in 0..4 {
+            buffer.push(i).unwrap();
+        }
+        assert!(!buffer.would_accept());
+        buffer.pop();
+        assert!(buffer.would_accept());
+    }
+}
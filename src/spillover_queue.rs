@@ -0,0 +1,192 @@
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::AtomicRingBufferMpmc;
+use crate::primitives::Arc;
+
+///An mpmc ring buffer that spills into a mutex protected VecDeque when full,
+///so pushes never drop data. The ring is the lock free fast path, the overflow
+///path takes a lock!
+pub struct SpilloverQueue<T, const N: usize> {
+    ring: Arc<AtomicRingBufferMpmc<T, N>>,
+    overflow: Mutex<VecDeque<T>>,
+    spilled: AtomicUsize,
+    ///Pushes that found the ring full and are heading for the overflow lock
+    spilling: AtomicUsize,
+}
+
+impl<T, const N: usize> SpilloverQueue<T, N> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ring: AtomicRingBufferMpmc::new(),
+            overflow: Mutex::new(VecDeque::new()),
+            spilled: AtomicUsize::new(0),
+            spilling: AtomicUsize::new(0),
+        })
+    }
+
+    ///Pushes into the ring, falling back to the overflow when the ring is full.
+    ///Once a push has found the ring full, later pushes skip the lock free path and
+    ///decide between the ring and the overflow under the overflow lock until it is
+    ///drained, so FIFO order is kept across both. Only pushes that overlap in time can
+    ///land in either order, as with any concurrent queue
+    pub fn push(&self, value: T) {
+        let value = if self.spilled.load(Ordering::Acquire) == 0
+            && self.spilling.load(Ordering::Acquire) == 0
+        {
+            match self.ring.push(value) {
+                Ok(()) => return,
+                Err(back) => back.into_inner(),
+            }
+        } else {
+            value
+        };
+
+        self.spilling.fetch_add(1, Ordering::AcqRel);
+        let mut overflow = self.overflow.lock();
+        //with nothing spilled yet the ring may have room again, and anything that got
+        //into it meanwhile was pushed before us
+        let value = if overflow.is_empty() {
+            match self.ring.push(value) {
+                Ok(()) => {
+                    self.spilling.fetch_sub(1, Ordering::AcqRel);
+                    return;
+                }
+                Err(back) => back.into_inner(),
+            }
+        } else {
+            value
+        };
+        overflow.push_back(value);
+        self.spilled.store(overflow.len(), Ordering::Release);
+        //spilled is already raised, so the fast path stays closed
+        self.spilling.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    ///Pops from the ring first, then from the overflow
+    pub fn pop(&self) -> Option<T> {
+        if let Some(value) = self.ring.pop() {
+            return Some(value);
+        }
+        if self.spilled.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+
+        let mut overflow = self.overflow.lock();
+        let value = overflow.pop_front();
+        self.spilled.store(overflow.len(), Ordering::Release);
+        value
+    }
+
+    ///Number of items currently sitting in the overflow
+    pub fn spilled(&self) -> usize {
+        self.spilled.load(Ordering::Acquire)
+    }
+
+    ///Panics if the queue's invariants are broken: the ring's own, see
+    ///[`AtomicRingBufferMpmc::check_invariants`], the `spilled` count matching what
+    ///the overflow holds and no push left marked as spilling. Only meaningful while no
+    ///push or pop is running, meant for tests and fuzzers
+    #[cfg(any(test, feature = "debug-checks"))]
+    pub fn check_invariants(&self) {
+        self.ring.check_invariants();
+        let overflow = self.overflow.lock();
+        let spilled = self.spilled.load(Ordering::Acquire);
+        assert_eq!(
+            spilled,
+            overflow.len(),
+            "spilled says {spilled} but the overflow holds {}",
+            overflow.len()
+        );
+        assert_eq!(
+            self.spilling.load(Ordering::Acquire),
+            0,
+            "a push is stuck spilling"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spills_and_keeps_fifo() {
+        let queue = SpilloverQueue::<i32, 4>::new();
+
+        for i in 0..10 {
+            queue.push(i);
+        }
+        assert_eq!(queue.spilled(), 6);
+
+        assert_eq!(queue.pop(), Some(0));
+        queue.push(10);
+        assert_eq!(queue.spilled(), 7);
+
+        for i in 1..=10 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.spilled(), 0);
+
+        queue.push(11);
+        assert_eq!(queue.spilled(), 0);
+        assert_eq!(queue.pop(), Some(11));
+    }
+
+    #[test]
+    fn test_fifo_per_producer_across_spills() {
+        let queue = SpilloverQueue::<(usize, usize), 4>::new();
+        let producers: Vec<_> = (0..4)
+            .map(|producer| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10_000 {
+                        queue.push((producer, i));
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = [0; 4];
+        while next.iter().any(|&n| n < 10_000) {
+            match queue.pop() {
+                Some((producer, i)) => {
+                    assert_eq!(i, next[producer], "producer {producer} out of order");
+                    next[producer] += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        queue.check_invariants();
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let queue = SpilloverQueue::<i32, 4>::new();
+        queue.check_invariants();
+        for i in 0..10 {
+            queue.push(i);
+            queue.check_invariants();
+            if i % 3 == 0 {
+                queue.pop();
+                queue.check_invariants();
+            }
+        }
+        while queue.pop().is_some() {
+            queue.check_invariants();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "but the overflow holds")]
+    fn test_check_invariants_catches_corruption() {
+        let queue = SpilloverQueue::<i32, 4>::new();
+        queue.spilled.store(1, Ordering::Relaxed);
+        queue.check_invariants();
+    }
+}
@@ -0,0 +1,326 @@
+use alloc::boxed::Box;
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+
+use crate::reclaim::Reclaimer;
+use crate::{Backoff, primitives::Arc};
+
+struct Node<T> {
+    value: ManuallyDrop<T>,
+    ///Never changes once the node is on the stack, stale readers may still load it
+    next: *mut Node<T>,
+}
+
+///Unbounded lock free LIFO stack, every push boxes a node and pushers and poppers race
+///on a single head pointer with CAS.
+///
+///The classic hazard is a pop that loads head `A` and its next `B`, gets preempted while
+///`A` is popped and freed and a new node lands at the same address, and then swaps in
+///the stale `B` (ABA), or simply reads `A.next` after `A` was freed. Here nodes are
+///never reused, each push boxes a fresh one, and a pop puts a hazard pointer on head
+///before reading it, a popped node is only freed once no hazard points to it. Until then
+///its address stays taken, so head can't come back to `A` while anyone still holds it
+///
+///```
+///use lockless_datastructures::TreiberStack;
+///
+///let stack = TreiberStack::new();
+///stack.push(1);
+///stack.push(2);
+///assert_eq!(stack.pop(), Some(2));
+///assert_eq!(stack.pop(), Some(1));
+///assert_eq!(stack.pop(), None);
+///```
+pub struct TreiberStack<T> {
+    head: AtomicPtr<Node<T>>,
+    reclaimer: Reclaimer<Node<T>>,
+}
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+impl<T> TreiberStack<T> {
+    ///Identifies the synchronization used, handy for labelling metrics
+    pub const KIND: &str = "treiber-stack";
+
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            reclaimer: Reclaimer::new(),
+        })
+    }
+
+    ///Pushes never read other nodes, so unlike pops they don't register with the reclaimer
+    pub fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value: ManuallyDrop::new(value),
+            next: ptr::null_mut(),
+        }));
+
+        let mut backoff = Backoff::new();
+        let mut head = self.head.load(SeqCst);
+        loop {
+            //not published yet, nobody else can see the node
+            unsafe { (*node).next = head };
+            match self.head.compare_exchange(head, node, SeqCst, SeqCst) {
+                Ok(_) => return,
+                Err(current) => {
+                    head = current;
+                    backoff.snooze();
+                }
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.reclaimer.enter();
+
+        let mut backoff = Backoff::new();
+        loop {
+            let head = guard.protect_load(0, &self.head);
+            if head.is_null() {
+                unsafe { self.reclaimer.leave(guard, None) };
+                return None;
+            }
+            //head can't be freed while it is protected, even if it was popped already
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange(head, next, SeqCst, SeqCst)
+                .is_ok()
+            {
+                //only the winner touches the value, the node is left for the reclaimer
+                let value = unsafe { ManuallyDrop::take(&mut (*head).value) };
+                unsafe { self.reclaimer.leave(guard, Some(head)) };
+                return Some(value);
+            }
+            backoff.snooze();
+        }
+    }
+
+    ///A snapshot, other threads may push or pop right after
+    pub fn is_empty(&self) -> bool {
+        self.head.load(SeqCst).is_null()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut boxed.value) };
+            node = boxed.next;
+        }
+        //popped nodes still waiting on the reclaimer are freed by its own drop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_lifo() {
+        let stack = TreiberStack::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+
+        for i in 0..10 {
+            stack.push(i);
+        }
+        assert!(!stack.is_empty());
+        for i in (5..10).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        stack.push(42);
+        assert_eq!(stack.pop(), Some(42));
+        assert_eq!(stack.pop(), Some(4));
+    }
+
+    #[test]
+    fn test_mpmc_concurrency() {
+        const NUM_PRODUCERS: usize = 4;
+        const NUM_CONSUMERS: usize = 4;
+        #[cfg(not(miri))]
+        const OPS_PER_THREAD: usize = 10_000;
+        #[cfg(miri)]
+        const OPS_PER_THREAD: usize = 100;
+
+        let stack: Arc<TreiberStack<usize>> = TreiberStack::new();
+        let barrier = Arc::new(Barrier::new(NUM_PRODUCERS + NUM_CONSUMERS));
+
+        let mut handles = vec![];
+
+        for p_id in 0..NUM_PRODUCERS {
+            let s = stack.clone();
+            let b = barrier.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+                for i in 0..OPS_PER_THREAD {
+                    s.push(p_id * OPS_PER_THREAD + i);
+                }
+            }));
+        }
+
+        let results = Arc::new(AtomicUsize::new(0));
+        let sum = Arc::new(AtomicUsize::new(0));
+        for _ in 0..NUM_CONSUMERS {
+            let s = stack.clone();
+            let b = barrier.clone();
+            let r = results.clone();
+            let total = sum.clone();
+            handles.push(thread::spawn(move || {
+                b.wait();
+
+                loop {
+                    match s.pop() {
+                        Some(value) => {
+                            total.fetch_add(value, Ordering::Relaxed);
+                            r.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => {
+                            if r.load(Ordering::Relaxed) == NUM_PRODUCERS * OPS_PER_THREAD {
+                                break;
+                            }
+                            std::thread::yield_now();
+                        }
+                    }
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let total = NUM_PRODUCERS * OPS_PER_THREAD;
+        assert_eq!(results.load(Ordering::SeqCst), total);
+        assert_eq!(sum.load(Ordering::SeqCst), total * (total - 1) / 2);
+        assert!(stack.is_empty());
+    }
+
+    static DROP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct DropTracker;
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            DROP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_drop_cleanup() {
+        DROP_COUNTER.store(0, Ordering::Relaxed);
+
+        {
+            let stack = TreiberStack::new();
+
+            for _ in 0..5 {
+                stack.push(DropTracker);
+            }
+
+            stack.pop();
+            stack.pop();
+
+            assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 2);
+        }
+
+        assert_eq!(DROP_COUNTER.load(Ordering::Relaxed), 5);
+    }
+
+    ///Two poppers that never stop overlapping, retired nodes still have to get freed
+    ///along the way instead of piling up until the stack is dropped
+    #[test]
+    fn test_retired_nodes_stay_bounded() {
+        #[cfg(not(miri))]
+        const OPS_PER_THREAD: usize = 50_000;
+        #[cfg(miri)]
+        const OPS_PER_THREAD: usize = 200;
+
+        let stack = TreiberStack::new();
+        let barrier = Arc::new(Barrier::new(2));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let s = stack.clone();
+                let b = barrier.clone();
+                thread::spawn(move || {
+                    b.wait();
+                    for i in 0..OPS_PER_THREAD {
+                        s.push(i);
+                        s.pop();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let reclaimer = &stack.reclaimer;
+        assert!(reclaimer.max_pending() <= reclaimer.records() * crate::reclaim::SCAN_BATCH);
+    }
+
+    static STRESS_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct StressTracker;
+
+    impl Drop for StressTracker {
+        fn drop(&mut self) {
+            STRESS_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    ///Every thread pushes and pops in turns so pops constantly overlap and nodes keep
+    ///going through the deferred list. Run it under Miri to catch a use after free
+    #[test]
+    fn test_reclamation_stress() {
+        const NUM_THREADS: usize = 8;
+        #[cfg(not(miri))]
+        const OPS_PER_THREAD: usize = 5_000;
+        #[cfg(miri)]
+        const OPS_PER_THREAD: usize = 50;
+
+        STRESS_DROPS.store(0, Ordering::Relaxed);
+        let popped = Arc::new(AtomicUsize::new(0));
+        {
+            let stack = TreiberStack::new();
+            let barrier = Arc::new(Barrier::new(NUM_THREADS));
+            let handles: Vec<_> = (0..NUM_THREADS)
+                .map(|_| {
+                    let s = stack.clone();
+                    let b = barrier.clone();
+                    let p = popped.clone();
+                    thread::spawn(move || {
+                        b.wait();
+                        for i in 0..OPS_PER_THREAD {
+                            s.push(StressTracker);
+                            if i % 3 != 0 && s.pop().is_some() {
+                                p.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+
+            //each popped value is dropped exactly once, right away
+            assert_eq!(
+                STRESS_DROPS.load(Ordering::Relaxed),
+                popped.load(Ordering::Relaxed)
+            );
+        }
+        //and the rest with the stack, none twice and none leaked
+        assert_eq!(
+            STRESS_DROPS.load(Ordering::Relaxed),
+            NUM_THREADS * OPS_PER_THREAD
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::atomic::{AtomicBool, Ordering, fence};
+use std::task::Waker;
+
+use parking_lot::Mutex;
+
+///Tasks waiting for something to change in a buffer. Registering the same task twice
+///keeps a single waker, and waking is a single load when nobody is waiting
+#[derive(Default)]
+pub(crate) struct WakerSet {
+    waiting: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl WakerSet {
+    ///The caller has to re-check its condition after this returns, otherwise a wake
+    ///that raced with the registration is lost
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        self.waiting.store(true, Ordering::Relaxed);
+        drop(wakers);
+        //pairs with the fence in wake_all, either we see the change or they see us
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn wake_all(&self) {
+        fence(Ordering::SeqCst);
+        if !self.waiting.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let wakers = {
+            let mut wakers = self.wakers.lock();
+            self.waiting.store(false, Ordering::Relaxed);
+            std::mem::take(&mut *wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+///A single waiting task, for buffers where only one side can wait, like the SPSC
+///consumer. Same protocol as `WakerSet`: register, re-check, then return Pending
+#[derive(Default)]
+pub(crate) struct WakerSlot {
+    waiting: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl WakerSlot {
+    ///Replaces the stored waker unless it already wakes the same task. The caller has to
+    ///re-check its condition after this returns
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut slot = self.waker.lock();
+        if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            *slot = Some(waker.clone());
+        }
+        self.waiting.store(true, Ordering::Relaxed);
+        drop(slot);
+        //pairs with the fence in wake, either we see the change or they see us
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if !self.waiting.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let waker = {
+            let mut slot = self.waker.lock();
+            self.waiting.store(false, Ordering::Relaxed);
+            slot.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl std::fmt::Debug for WakerSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WakerSlot")
+            .field("waiting", &self.waker.lock().is_some())
+            .finish()
+    }
+}
+
+///Times `pop_cooperative` yields to the runtime before giving up on an empty buffer
+pub(crate) const COOPERATIVE_RETRIES: usize = 64;
+
+///Returns Pending once so the executor can run other tasks, works with any runtime
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+impl std::fmt::Debug for WakerSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WakerSet")
+            .field("waiting", &self.wakers.lock().len())
+            .finish()
+    }
+}